@@ -0,0 +1,242 @@
+use crate::state::{AppState, MotherlodeReading, Survey};
+use crate::survey::{MAX_RESOURCE_LEN, RESOURCE_PUNCTUATION};
+use chrono::{Local, NaiveDate, TimeZone};
+use regex::Regex;
+use serde::Serialize;
+
+fn survey_regex() -> Regex {
+    let name = format!(
+        "[A-Za-z0-9{}]{{1,{MAX_RESOURCE_LEN}}}",
+        regex::escape(RESOURCE_PUNCTUATION)
+    );
+    Regex::new(&format!(
+        r"\[Status\] The ({name}) is (\d+)m (east|west) and (\d+)m (north|south)\."
+    ))
+    .unwrap()
+}
+
+fn collected_regex() -> Regex {
+    Regex::new(r"\[Status\] (.+?) collected!").unwrap()
+}
+
+fn zone_regex() -> Regex {
+    Regex::new(r"Entering Area: (.+)").unwrap()
+}
+
+/// `yy-mm-dd hh:mm:ss` (or a four-digit year) at the start of a log line.
+fn timestamp_regex() -> Regex {
+    Regex::new(r"^(\d{2}|\d{4})-(\d{2})-(\d{2}) (\d{2}):(\d{2}):(\d{2})").unwrap()
+}
+
+fn motherlode_regex() -> Regex {
+    Regex::new(r"\[Status\] The treasure is (\d+) meters from here\.").unwrap()
+}
+
+/// A chat line the survey state machine cares about.
+#[derive(Debug, Clone)]
+pub enum ChatLine {
+    Zone(String),
+    Survey(Survey),
+    /// Name of the item picked up
+    Collected(String),
+    /// Straight-line distance reported by a motherlode map
+    Motherlode(u32),
+}
+
+impl ChatLine {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ChatLine::Zone(_) => "zone",
+            ChatLine::Survey(_) => "survey",
+            ChatLine::Collected(_) => "collected",
+            ChatLine::Motherlode(_) => "motherlode",
+        }
+    }
+}
+
+/// Recognizes survey-related lines in the game's chat log.
+pub struct ChatParser {
+    survey_re: Regex,
+    collected_re: Regex,
+    zone_re: Regex,
+    motherlode_re: Regex,
+    timestamp_re: Regex,
+}
+
+impl ChatParser {
+    pub fn new() -> Self {
+        Self {
+            survey_re: survey_regex(),
+            collected_re: collected_regex(),
+            zone_re: zone_regex(),
+            motherlode_re: motherlode_regex(),
+            timestamp_re: timestamp_regex(),
+        }
+    }
+
+    /// Unix time (ms) of a log line's leading timestamp, read as local time.
+    pub fn timestamp(&self, line: &str) -> Option<u64> {
+        let caps = self.timestamp_re.captures(line)?;
+        let field = |i: usize| caps[i].parse::<u32>().ok();
+        let year = match field(1)? {
+            y if y < 100 => 2000 + y,
+            y => y,
+        };
+        let date = NaiveDate::from_ymd_opt(year as i32, field(2)?, field(3)?)?;
+        let time = date.and_hms_opt(field(4)?, field(5)?, field(6)?)?;
+        let local = Local.from_local_datetime(&time).earliest()?;
+        u64::try_from(local.timestamp_millis()).ok()
+    }
+
+    pub fn parse(&self, line: &str) -> Option<ChatLine> {
+        if let Some(caps) = self.zone_re.captures(line) {
+            return Some(ChatLine::Zone(caps[1].trim().to_string()));
+        }
+
+        if let Some(caps) = self.survey_re.captures(line) {
+            let resource = caps[1].to_string();
+            let mut dx: i32 = caps[2].parse().unwrap_or(0);
+            if &caps[3] == "west" {
+                dx = -dx;
+            }
+            let mut dy: i32 = caps[4].parse().unwrap_or(0);
+            if &caps[5] == "north" {
+                dy = -dy;
+            }
+            return Some(ChatLine::Survey(Survey::new(resource, dx, dy)));
+        }
+
+        if let Some(caps) = self.motherlode_re.captures(line) {
+            return caps[1].parse().ok().map(ChatLine::Motherlode);
+        }
+
+        self.collected_re
+            .captures(line)
+            .map(|caps| ChatLine::Collected(caps[1].to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecognizedLine {
+    pub line: String,
+    pub kind: &'static str,
+}
+
+/// What `import_chat_text` made of a pasted block.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    pub recognized: Vec<RecognizedLine>,
+    pub ignored: Vec<String>,
+    /// Surveys in the new batch after deduplication
+    pub surveys: usize,
+}
+
+/// Build a batch from a block of pasted chat text.
+///
+/// Every survey line goes into the batch (deduplicated by resource), the
+/// last zone line sets the zone, and motherlode distances are recorded.
+/// `collected!` lines after the last survey line are applied to the new
+/// batch the way the watcher would apply them. If the text has no survey
+/// lines the current batch is left alone.
+pub fn import_chat_text(state: &mut AppState, text: &str) -> ImportReport {
+    let parser = ChatParser::new();
+    let mut recognized = Vec::new();
+    let mut ignored = Vec::new();
+    let mut parsed = Vec::new();
+
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        match parser.parse(line) {
+            Some(chat_line) => {
+                recognized.push(RecognizedLine {
+                    line: line.to_string(),
+                    kind: chat_line.kind(),
+                });
+                parsed.push(chat_line);
+            }
+            None => ignored.push(line.to_string()),
+        }
+    }
+
+    let last_survey = parsed
+        .iter()
+        .rposition(|l| matches!(l, ChatLine::Survey(_)));
+    let mut batch = Vec::new();
+    let mut collected = Vec::new();
+    for (i, line) in parsed.into_iter().enumerate() {
+        match line {
+            ChatLine::Zone(zone) => state.zone = zone,
+            ChatLine::Survey(survey) => {
+                state.log_reading(&survey, None);
+                batch.push(survey)
+            }
+            ChatLine::Motherlode(distance_m) => state.motherlode_readings.push(MotherlodeReading {
+                pos: state.player_pos,
+                distance_m,
+            }),
+            ChatLine::Collected(item) if last_survey.is_some_and(|last| i > last) => {
+                collected.push(item)
+            }
+            ChatLine::Collected(item) => state.log_collection(&item, None, None),
+        }
+    }
+
+    if !batch.is_empty() {
+        state.start_batch(&batch);
+        for item in collected {
+            let Some(index) = state.survey_for_item(&item) else {
+                state.log_collection(&item, None, None);
+                continue;
+            };
+            state.collect(index, item, None);
+            state.reroute();
+        }
+    }
+
+    ImportReport {
+        recognized,
+        ignored,
+        surveys: state.surveys.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::SurveyStatus;
+
+    #[test]
+    fn pasted_collections_mark_their_own_resource() {
+        let mut state = AppState::default();
+        let report = import_chat_text(
+            &mut state,
+            "[Status] The Copper Ore is 10m east and 20m north.
+             [Status] The Tin Ore is 30m west and 5m south.
+             [Status] Tin Ore collected!",
+        );
+        assert_eq!(report.surveys, 2);
+        let status = |resource: &str| {
+            state
+                .surveys
+                .iter()
+                .find(|s| s.resource == resource)
+                .map(|s| s.status)
+        };
+        assert_eq!(status("Tin Ore"), Some(SurveyStatus::Found));
+        assert_eq!(status("Copper Ore"), Some(SurveyStatus::Pending));
+        assert_eq!(state.collected_items, ["Tin Ore"]);
+    }
+
+    #[test]
+    fn other_pasted_collections_go_to_the_next_target() {
+        let mut state = AppState::default();
+        import_chat_text(
+            &mut state,
+            "[Status] The Copper Ore is 10m east and 20m north.
+             [Status] The Tin Ore is 30m west and 5m south.
+             [Status] Tin Ore collected!
+             [Status] Native Copper collected!",
+        );
+        assert!(state.surveys.iter().all(|s| !s.is_pending()));
+        assert_eq!(state.collected_items, ["Tin Ore", "Native Copper"]);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::settings::Settings;
+use crate::state::{AppState, SurveyStatus};
+use crate::survey::compute_render_payload;
+use crate::sync::{SyncBatch, SyncHub, SyncSettings};
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+const USAGE: &str = "\
+Usage: survey-app render <session.json> <out.png|out.svg> [--width <px, up to 4096>] [--settings <settings.json>]
+       survey-app sync <session.json> --key <group key> [--port <port>] [--peer <host:port>]...
+
+render draws the saved batch (map, dots, route and player) to an image. The
+session file is session.json in the app's data directory.
+
+sync runs a headless sync peer on a session file, saving whatever peers
+with the same group key share. Type `list`, or `found`, `skip`, `unreachable` or `pending` and a
+survey number, to change it and send it to peers.";
+
+/// Handle command-line subcommands. Returns the exit code when one ran, or
+/// `None` to start the app as usual.
+pub fn run(args: &[String]) -> Option<i32> {
+    match args.first().map(String::as_str) {
+        Some("render") => Some(match render(&args[1..]) {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("{e}\n\n{USAGE}");
+                1
+            }
+        }),
+        Some("sync") => Some(match sync(&args[1..]) {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("{e}\n\n{USAGE}");
+                1
+            }
+        }),
+        Some("--help" | "-h" | "help") => {
+            println!("{USAGE}");
+            Some(0)
+        }
+        _ => None,
+    }
+}
+
+fn render(args: &[String]) -> Result<(), String> {
+    let mut positional = Vec::new();
+    let mut width = 1024;
+    let mut settings = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--width" => {
+                width = iter
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .ok_or("--width needs a number of pixels")?;
+            }
+            "--settings" => {
+                settings = Some(PathBuf::from(iter.next().ok_or("--settings needs a file")?));
+            }
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+    let [session, out] = positional.as_slice() else {
+        return Err("Expected a session file and an output file".into());
+    };
+    if !session.exists() {
+        return Err(format!("No such session file: {}", session.display()));
+    }
+
+    let mut state = AppState::default();
+    if let Some(path) = settings {
+        state.settings = Settings::load(&path);
+    }
+    state.open_session(session.clone())?;
+    crate::render::render_to_file(&compute_render_payload(&state), out, width)?;
+    println!("Wrote {}", out.display());
+    Ok(())
+}
+
+fn print_batch(s: &AppState) {
+    println!("{} — {}", s.zone, compute_render_payload(s).summary);
+    for (i, survey) in s.surveys.iter().enumerate() {
+        println!(
+            "  {:>2}. {} ({}, {}) {:?}",
+            i + 1,
+            survey.resource,
+            survey.dx,
+            survey.dy,
+            survey.status
+        );
+    }
+}
+
+fn sync(args: &[String]) -> Result<(), String> {
+    let mut session = None;
+    let mut settings = SyncSettings {
+        enabled: true,
+        ..SyncSettings::default()
+    };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--port" => {
+                settings.port = iter
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .ok_or("--port needs a port number")?;
+            }
+            "--peer" => settings
+                .peers
+                .push(iter.next().ok_or("--peer needs host:port")?.clone()),
+            "--key" => settings.key = iter.next().ok_or("--key needs the group key")?.clone(),
+            _ => session = Some(PathBuf::from(arg)),
+        }
+    }
+    let session = session.ok_or("Expected a session file")?;
+
+    // A new file is fine; one we can't read would be overwritten
+    let mut state = AppState::default();
+    if session.exists() {
+        state.open_session(session)?;
+    } else {
+        state.load_session(session);
+    }
+    let state = Arc::new(Mutex::new(state));
+    let hub = SyncHub::start(
+        &settings,
+        state.clone(),
+        Arc::new(|s: &AppState, _| {
+            if let Err(e) = s.save_session() {
+                eprintln!("Failed to save session: {e}");
+            }
+            print_batch(s);
+        }),
+    )?;
+    println!("Syncing on port {}", hub.port());
+    print_batch(&state.lock().unwrap());
+
+    for line in std::io::stdin().lock().lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        let mut words = line.split_whitespace();
+        let status = match words.next() {
+            Some("list") => {
+                print_batch(&state.lock().unwrap());
+                continue;
+            }
+            Some("found") => SurveyStatus::Found,
+            Some("skip") => SurveyStatus::Skipped,
+            Some("unreachable") => SurveyStatus::Unreachable,
+            Some("pending") => SurveyStatus::Pending,
+            Some(other) => {
+                eprintln!("Unknown command: {other}");
+                continue;
+            }
+            None => continue,
+        };
+        let mut s = state.lock().unwrap();
+        let Some(index) = words
+            .next()
+            .and_then(|n| n.parse::<usize>().ok())
+            .and_then(|n| n.checked_sub(1))
+            .filter(|&i| i < s.surveys.len())
+        else {
+            eprintln!("Expected a survey number from the list");
+            continue;
+        };
+        s.checkpoint();
+        s.surveys[index].set_status(status);
+        s.reroute();
+        if let Some(batch) = s.complete_batch() {
+            hub.publish(SyncBatch::from_archived(&batch));
+        }
+        hub.publish(SyncBatch::from_state(&s));
+        if let Err(e) = s.save_session() {
+            eprintln!("Failed to save session: {e}");
+        }
+        print_batch(&s);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Survey;
+
+    fn temp(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("survey-cli-{}-{name}", std::process::id()))
+    }
+
+    fn args(list: &[&PathBuf], extra: &[&str]) -> Vec<String> {
+        list.iter()
+            .map(|p| p.display().to_string())
+            .chain(extra.iter().map(|s| s.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn render_refuses_a_damaged_session() {
+        let (session, out) = (temp("damaged.json"), temp("damaged.svg"));
+        std::fs::write(&session, "{ not json").unwrap();
+        let err = render(&args(&[&session, &out], &[])).unwrap_err();
+        assert!(err.contains("Not a session file"), "{err}");
+        assert!(!out.exists());
+        let _ = std::fs::remove_file(&session);
+    }
+
+    #[test]
+    fn render_caps_the_width() {
+        let (session, out) = (temp("saved.json"), temp("saved.svg"));
+        let mut state = AppState::default();
+        state.load_session(session.clone());
+        state.start_batch(&[Survey::new("Copper Ore".into(), 10, 20)]);
+        state.save_session().unwrap();
+
+        assert!(render(&args(&[&session, &out], &["--width", "100000"])).is_err());
+        assert!(!out.exists());
+        render(&args(&[&session, &out], &["--width", "800"])).unwrap();
+        assert!(std::fs::read_to_string(&out).unwrap().contains("<svg"));
+
+        let _ = std::fs::remove_file(&session);
+        let _ = std::fs::remove_file(&out);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::state::AppState;
+use crate::survey::{survey_position, zone_dimensions};
+use serde::Serialize;
+
+const COMPASS: [&str; 8] = [
+    "north",
+    "north-east",
+    "east",
+    "south-east",
+    "south",
+    "south-west",
+    "west",
+    "north-west",
+];
+
+/// One step of the route, from the previous stop to a survey.
+#[derive(Debug, Clone, Serialize)]
+pub struct Leg {
+    /// Survey index
+    pub index: usize,
+    /// Position in the route, as labelled on the map
+    pub label: usize,
+    pub resource: String,
+    pub distance_m: f64,
+    /// Degrees clockwise from north
+    pub bearing: f64,
+    pub text: String,
+}
+
+/// Compass point for a bearing in degrees clockwise from north.
+fn compass(bearing: f64) -> &'static str {
+    COMPASS[((bearing / 45.0).round() as usize) % 8]
+}
+
+/// Round long distances to 10m; nobody paces out 317m.
+fn spoken_distance(d: f64) -> f64 {
+    if d >= 100.0 {
+        (d / 10.0).round() * 10.0
+    } else {
+        d.round()
+    }
+}
+
+/// Directions for the local player's route, starting where they stand.
+pub fn route_legs(state: &AppState) -> Vec<Leg> {
+    let (zw, zh) = zone_dimensions(&state.zone);
+    let mut from = (
+        state.player_pos.0 * zw as f64,
+        state.player_pos.1 * zh as f64,
+    );
+
+    let mut legs = Vec::new();
+    for (pos, &index) in state.path_order.iter().enumerate() {
+        let Some(survey) = state.surveys.get(index).filter(|s| s.is_pending()) else {
+            continue;
+        };
+        let to = survey_position(state.survey_origin, survey, &state.zone);
+        let (ex, ey) = (to.0 - from.0, to.1 - from.1);
+        let distance_m = (ex * ex + ey * ey).sqrt();
+        // y grows southward, so north is -y
+        let bearing = ex.atan2(-ey).to_degrees().rem_euclid(360.0);
+        let label = pos + 1;
+        let text = if distance_m < 1.0 {
+            format!("{} ({label}) is right here", survey.resource)
+        } else {
+            format!(
+                "Go {}m {} to {} ({label})",
+                spoken_distance(distance_m),
+                compass(bearing),
+                survey.resource
+            )
+        };
+        legs.push(Leg {
+            index,
+            label,
+            resource: survey.resource.clone(),
+            distance_m,
+            bearing,
+            text,
+        });
+        from = to;
+    }
+    legs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Survey;
+
+    fn directions_to(dx: i32, dy: i32) -> String {
+        let mut s = AppState::default();
+        s.start_batch(&[Survey::new("Copper Ore".into(), dx, dy)]);
+        route_legs(&s).remove(0).text
+    }
+
+    #[test]
+    fn bearings_split_into_eight_points() {
+        let points: Vec<&str> = [
+            0.0, 22.4, 22.6, 90.0, 157.6, 202.4, 202.6, 270.0, 337.6, 359.9,
+        ]
+        .into_iter()
+        .map(compass)
+        .collect();
+        assert_eq!(
+            points,
+            [
+                "north",
+                "north",
+                "north-east",
+                "east",
+                "south",
+                "south",
+                "south-west",
+                "west",
+                "north",
+                "north"
+            ]
+        );
+    }
+
+    #[test]
+    fn legs_read_as_directions() {
+        assert_eq!(directions_to(0, -40), "Go 40m north to Copper Ore (1)");
+        assert_eq!(
+            directions_to(-30, 30),
+            "Go 42m south-west to Copper Ore (1)"
+        );
+        assert_eq!(directions_to(317, 0), "Go 320m east to Copper Ore (1)");
+        assert_eq!(directions_to(0, 0), "Copper Ore (1) is right here");
+    }
+}
@@ -0,0 +1,195 @@
+use crate::heatmap::DensityGrid;
+use crate::state::Survey;
+use crate::survey::zone_dimensions;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Spread (meters) of the match between a survey and a known spawn point.
+const SPAWN_SIGMA: f64 = 40.0;
+/// Estimates within this many meters of each other count as the same spot.
+const CONFIDENCE_RADIUS: f64 = 50.0;
+/// Upper bound on candidate origins evaluated per estimate.
+const MAX_CELLS: f64 = 40_000.0;
+/// Cell size (meters) of the grids spawn history is binned into.
+const SPAWN_CELL: f64 = 20.0;
+
+/// A place a resource was actually collected, in zone meters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpawnPoint {
+    pub zone: String,
+    pub resource: String,
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionEstimate {
+    /// Most likely survey origin, 0.0–1.0 relative
+    pub pos: (f64, f64),
+    /// Share of the likelihood within `CONFIDENCE_RADIUS` of `pos` (0.0–1.0)
+    pub confidence: f64,
+}
+
+/// Estimate where the batch was surveyed from using only the `dx`/`dy`
+/// offsets. Every survey must land inside the zone; when `spawns` has
+/// history for the zone, origins that put surveys on known spawn spots of
+/// the same resource are preferred. Returns `None` if no origin fits.
+pub fn estimate_origin(
+    surveys: &[Survey],
+    zone: &str,
+    spawns: &[SpawnPoint],
+) -> Option<PositionEstimate> {
+    let (zw, zh) = zone_dimensions(zone);
+    let zw = zw as f64;
+    let zh = zh as f64;
+
+    // Feasible origins (meters): every origin + offset must lie in [0, size]
+    let x_lo = -(surveys.iter().map(|s| s.dx).min()? as f64);
+    let x_hi = zw - surveys.iter().map(|s| s.dx).max()? as f64;
+    let y_lo = -(surveys.iter().map(|s| s.dy).min()? as f64);
+    let y_hi = zh - surveys.iter().map(|s| s.dy).max()? as f64;
+    if x_lo > x_hi || y_lo > y_hi {
+        return None;
+    }
+
+    let area = ((x_hi - x_lo) * (y_hi - y_lo)).max(1.0);
+    let step = (area / MAX_CELLS).sqrt().max(5.0);
+
+    // Known spawns of each surveyed resource in this zone, binned so scoring
+    // doesn't slow down as history grows
+    let cols = (zw / SPAWN_CELL).ceil() as usize;
+    let rows = (zh / SPAWN_CELL).ceil() as usize;
+    let mut grids: HashMap<&str, Option<(DensityGrid, f64)>> = HashMap::new();
+    for s in surveys {
+        grids.entry(&s.resource).or_insert_with(|| {
+            let points: Vec<(f64, f64)> = spawns
+                .iter()
+                .filter(|p| p.zone == zone && p.resource == s.resource)
+                .map(|p| (p.x, p.y))
+                .collect();
+            (!points.is_empty()).then(|| {
+                let n = points.len() as f64;
+                (
+                    DensityGrid::new(points, cols, rows, SPAWN_CELL, SPAWN_SIGMA),
+                    n,
+                )
+            })
+        });
+    }
+    let known: Vec<_> = surveys
+        .iter()
+        .filter_map(|s| {
+            let grid = grids[s.resource.as_str()].as_ref()?;
+            Some(((s.dx as f64, s.dy as f64), grid))
+        })
+        .collect();
+
+    // Log-likelihood of each candidate origin. Surveys with no spawn history
+    // add nothing beyond the in-bounds constraint.
+    let mut cells: Vec<(f64, f64, f64)> = Vec::new();
+    let mut y = y_lo;
+    while y <= y_hi {
+        let mut x = x_lo;
+        while x <= x_hi {
+            let score: f64 = known
+                .iter()
+                .map(|&((dx, dy), (grid, n))| (grid.at(x + dx, y + dy) / n + 1e-6).ln())
+                .sum();
+            cells.push((x, y, score));
+            x += step;
+        }
+        y += step;
+    }
+
+    let max_score = cells.iter().map(|c| c.2).fold(f64::NEG_INFINITY, f64::max);
+    let best = if cells.iter().all(|c| (c.2 - max_score).abs() < 1e-9) {
+        // No spawn history to go on: the middle of the feasible area
+        ((x_lo + x_hi) / 2.0, (y_lo + y_hi) / 2.0)
+    } else {
+        cells
+            .iter()
+            .find(|c| c.2 == max_score)
+            .map(|c| (c.0, c.1))?
+    };
+
+    let weights: Vec<f64> = cells.iter().map(|c| (c.2 - max_score).exp()).collect();
+    let total: f64 = weights.iter().sum();
+    let near: f64 = cells
+        .iter()
+        .zip(&weights)
+        .filter(|(c, _)| {
+            (c.0 - best.0).powi(2) + (c.1 - best.1).powi(2) <= CONFIDENCE_RADIUS.powi(2)
+        })
+        .map(|(_, w)| w)
+        .sum();
+
+    Some(PositionEstimate {
+        pos: (best.0 / zw, best.1 / zh),
+        confidence: if total > 0.0 { near / total } else { 0.0 },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(resource: &str, x: f64, y: f64) -> SpawnPoint {
+        SpawnPoint {
+            zone: "Serbule".into(),
+            resource: resource.into(),
+            x,
+            y,
+        }
+    }
+
+    #[test]
+    fn spawn_history_recovers_the_origin() {
+        let (zw, zh) = zone_dimensions("Serbule");
+        let origin = (1000.0, 1200.0);
+        let surveys = [
+            Survey::new("Copper Ore".into(), 150, -80),
+            Survey::new("Tin Ore".into(), -220, 40),
+            Survey::new("Iron Ore".into(), 60, 310),
+        ];
+        let mut spawns: Vec<SpawnPoint> = surveys
+            .iter()
+            .map(|s| spawn(&s.resource, origin.0 + s.dx as f64, origin.1 + s.dy as f64))
+            .collect();
+        // Plenty of history elsewhere, and in another zone
+        for i in 0..2000 {
+            let (x, y) = ((i * 37 % 2300) as f64, (i * 53 % 2400) as f64);
+            spawns.push(spawn("Gold Ore", x, y));
+            spawns.push(SpawnPoint {
+                zone: "Eltibule".into(),
+                ..spawn("Copper Ore", x, y)
+            });
+        }
+
+        let estimate = estimate_origin(&surveys, "Serbule", &spawns).unwrap();
+        let (x, y) = (estimate.pos.0 * zw as f64, estimate.pos.1 * zh as f64);
+        assert!(
+            (x - origin.0).abs() < 30.0 && (y - origin.1).abs() < 30.0,
+            "{x}, {y}"
+        );
+        assert!(estimate.confidence > 0.5, "{}", estimate.confidence);
+    }
+
+    #[test]
+    fn without_history_the_middle_of_where_surveys_fit() {
+        let (zw, zh) = zone_dimensions("Serbule");
+        let surveys = [
+            Survey::new("Copper Ore".into(), 1000, 0),
+            Survey::new("Tin Ore".into(), -1000, 0),
+        ];
+        let estimate = estimate_origin(&surveys, "Serbule", &[]).unwrap();
+        assert!((estimate.pos.0 * zw as f64 - zw as f64 / 2.0).abs() < 1.0);
+        assert!((estimate.pos.1 - 0.5).abs() < 1e-9);
+
+        // Wider apart than the zone: nowhere fits
+        let surveys = [
+            Survey::new("Copper Ore".into(), zw as i32, 0),
+            Survey::new("Tin Ore".into(), -(zh as i32), 0),
+        ];
+        assert!(estimate_origin(&surveys, "Serbule", &[]).is_none());
+    }
+}
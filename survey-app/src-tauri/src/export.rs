@@ -0,0 +1,394 @@
+use crate::state::{now_ms, AppState, ArchivedBatch, Mode, Survey, SurveyStatus};
+use crate::survey::{known_zone_dimensions, survey_position, zone_dimensions};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// One survey with its absolute position in zone meters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SurveyExport {
+    pub resource: String,
+    pub dx: i32,
+    pub dy: i32,
+    pub x: f64,
+    pub y: f64,
+    pub status: SurveyStatus,
+    pub status_changed_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchExport {
+    pub zone: String,
+    /// Where the batch was surveyed from (0.0–1.0 relative)
+    pub survey_origin: (f64, f64),
+    /// Unix time (ms)
+    pub started_at: Option<u64>,
+    /// `None` for the batch still in progress
+    pub completed_at: Option<u64>,
+    pub distance_walked_m: f64,
+    pub items_collected: Vec<String>,
+    pub surveys: Vec<SurveyExport>,
+    /// Where the player walked from before each collection (0.0–1.0
+    /// relative). Only kept for the current batch.
+    pub trail: Vec<(f64, f64)>,
+    /// Indices into `surveys`: collected ones in collection order, then
+    /// (for the current batch) the pending ones in planned order
+    pub route: Vec<usize>,
+}
+
+/// Everything needed to analyze or restore a session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionExport {
+    pub exported_at: u64,
+    /// 0.0–1.0 relative
+    pub player_pos: (f64, f64),
+    pub current: BatchExport,
+    pub archive: Vec<BatchExport>,
+}
+
+fn export_surveys(origin: (f64, f64), surveys: &[Survey], zone: &str) -> Vec<SurveyExport> {
+    surveys
+        .iter()
+        .map(|s| {
+            let (x, y) = survey_position(origin, s, zone);
+            SurveyExport {
+                resource: s.resource.clone(),
+                dx: s.dx,
+                dy: s.dy,
+                x,
+                y,
+                status: s.status,
+                status_changed_at: s.status_changed_at,
+            }
+        })
+        .collect()
+}
+
+impl BatchExport {
+    fn current(state: &AppState) -> Self {
+        let mut route: Vec<usize> = (0..state.surveys.len())
+            .filter(|&i| state.surveys[i].status == SurveyStatus::Found)
+            .collect();
+        route.sort_by_key(|&i| state.surveys[i].status_changed_at);
+        route.extend(
+            state
+                .path_order
+                .iter()
+                .filter(|&&i| state.surveys.get(i).is_some_and(Survey::is_pending)),
+        );
+
+        Self {
+            zone: state.zone.clone(),
+            survey_origin: state.survey_origin,
+            started_at: state.batch_started_at,
+            completed_at: None,
+            distance_walked_m: state.distance_walked(),
+            items_collected: state.collected_items.clone(),
+            surveys: export_surveys(state.survey_origin, &state.surveys, &state.zone),
+            trail: state.trail.clone(),
+            route,
+        }
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if known_zone_dimensions(&self.zone).is_none() {
+            return Err(format!("Unknown zone: {}", self.zone));
+        }
+        if self.route.iter().any(|&i| i >= self.surveys.len()) {
+            return Err("Route refers to a survey that isn't in the batch".into());
+        }
+        Ok(())
+    }
+
+    fn to_surveys(&self) -> Vec<Survey> {
+        self.surveys
+            .iter()
+            .map(|s| Survey {
+                status: s.status,
+                status_changed_at: s.status_changed_at,
+                ..Survey::new(s.resource.clone(), s.dx, s.dy)
+            })
+            .collect()
+    }
+
+    fn archived(batch: &ArchivedBatch) -> Self {
+        Self {
+            zone: batch.zone.clone(),
+            survey_origin: batch.survey_origin,
+            started_at: batch.started_at,
+            completed_at: Some(batch.completed_at),
+            distance_walked_m: batch.distance_walked_m,
+            items_collected: batch.items_collected.clone(),
+            surveys: export_surveys(batch.survey_origin, &batch.surveys, &batch.zone),
+            trail: Vec::new(),
+            route: batch.route.clone(),
+        }
+    }
+}
+
+impl SessionExport {
+    pub fn from_state(state: &AppState) -> Self {
+        Self {
+            exported_at: now_ms(),
+            player_pos: state.player_pos,
+            current: BatchExport::current(state),
+            archive: state.archive.iter().map(BatchExport::archived).collect(),
+        }
+    }
+
+    /// Archived batches oldest first, then the current one, labelled for output.
+    fn batches(&self) -> impl Iterator<Item = (String, &BatchExport)> {
+        self.archive
+            .iter()
+            .enumerate()
+            .map(|(i, b)| ((i + 1).to_string(), b))
+            .chain(std::iter::once(("current".to_string(), &self.current)))
+    }
+}
+
+/// Quote a CSV field when it needs it.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn opt(value: Option<u64>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// One row per survey across every batch.
+pub fn to_csv(session: &SessionExport) -> String {
+    let mut out = String::from(
+        "batch,zone,resource,dx,dy,x,y,status,status_changed_at,batch_started_at,batch_completed_at\n",
+    );
+    for (label, batch) in session.batches() {
+        for s in &batch.surveys {
+            let status = serde_json::to_value(s.status).unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "{},{},{},{},{},{:.1},{:.1},{},{},{},{}",
+                label,
+                csv_field(&batch.zone),
+                csv_field(&s.resource),
+                s.dx,
+                s.dy,
+                s.x,
+                s.y,
+                status.as_str().unwrap_or_default(),
+                opt(s.status_changed_at),
+                opt(batch.started_at),
+                opt(batch.completed_at),
+            );
+        }
+    }
+    out
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// GPX-style route file: one `<rte>` per batch, starting at the survey
+/// origin, with waypoints in zone meters (x east, y south) instead of lat/lon.
+pub fn to_route(session: &SessionExport) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<gpx version=\"1.1\" creator=\"Survey Helper\">\n",
+    );
+    for (label, batch) in session.batches() {
+        let (zw, zh) = zone_dimensions(&batch.zone);
+        let _ = writeln!(
+            out,
+            "  <rte>\n    <name>Batch {}</name>\n    <desc>{}</desc>",
+            label,
+            xml_escape(&batch.zone)
+        );
+        let start = (
+            batch.survey_origin.0 * zw as f64,
+            batch.survey_origin.1 * zh as f64,
+        );
+        let waypoints = std::iter::once(("Start".to_string(), start, None)).chain(
+            batch
+                .route
+                .iter()
+                .filter_map(|&i| batch.surveys.get(i))
+                .map(|s| {
+                    let time = (s.status == SurveyStatus::Found)
+                        .then_some(s.status_changed_at)
+                        .flatten();
+                    (s.resource.clone(), (s.x, s.y), time)
+                }),
+        );
+        for (name, (x, y), time) in waypoints {
+            let _ = write!(
+                out,
+                "    <rtept x=\"{x:.1}\" y=\"{y:.1}\"><name>{}</name>",
+                xml_escape(&name)
+            );
+            if let Some(t) = time {
+                let _ = write!(out, "<time>{t}</time>");
+            }
+            out.push_str("</rtept>\n");
+        }
+        out.push_str("  </rte>\n");
+    }
+    out.push_str("</gpx>\n");
+    out
+}
+
+/// Replace the current batch with one saved by `export_session` as JSON.
+/// The player, survey origin and trail go back where they were; the route
+/// is planned afresh. The file's archived batches are added to ours,
+/// skipping ones we already have.
+pub fn import_session(state: &mut AppState, path: &Path) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let session: SessionExport =
+        serde_json::from_str(&text).map_err(|e| format!("Not a session export: {e}"))?;
+    session.current.check()?;
+    for batch in &session.archive {
+        batch.check()?;
+    }
+
+    let current = &session.current;
+    let clamp = |(x, y): (f64, f64)| (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0));
+    state.zone = current.zone.clone();
+    state.surveys = current.to_surveys();
+    state.player_pos = clamp(session.player_pos);
+    state.clear_plan();
+    state.survey_origin = clamp(current.survey_origin);
+    state.trail = current.trail.iter().copied().map(clamp).collect();
+    state.batch_started_at = current.started_at;
+    state.collected_items = current.items_collected.clone();
+    let imported: Vec<ArchivedBatch> = session
+        .archive
+        .iter()
+        .map(|b| ArchivedBatch {
+            zone: b.zone.clone(),
+            survey_origin: b.survey_origin,
+            surveys: b.to_surveys(),
+            route: b.route.clone(),
+            started_at: b.started_at,
+            completed_at: b.completed_at.unwrap_or(session.exported_at),
+            distance_walked_m: b.distance_walked_m,
+            items_collected: b.items_collected.clone(),
+        })
+        .collect();
+    for batch in imported {
+        if !state
+            .archive
+            .iter()
+            .any(|b| b.completed_at == batch.completed_at)
+        {
+            state.archive.push(batch);
+        }
+    }
+    state.removed.clear();
+    state.group_starts.clear();
+    state.record_buffer.clear();
+    state.queued.clear();
+    state.motherlode_readings.clear();
+    state.position_estimate = None;
+
+    if state.surveys.iter().any(Survey::is_pending) {
+        state.mode = Mode::Find;
+        state.reroute();
+    } else {
+        state.mode = Mode::Record;
+    }
+    Ok(())
+}
+
+/// Write the session to `path` as "csv", "json" or "route".
+pub fn export_session(state: &AppState, path: &Path, format: &str) -> Result<(), String> {
+    let session = SessionExport::from_state(state);
+    let contents = match format {
+        "csv" => to_csv(&session),
+        "json" => serde_json::to_string_pretty(&session).map_err(|e| e.to_string())?,
+        "route" => to_route(&session),
+        other => return Err(format!("Unknown export format: {other}")),
+    };
+    std::fs::write(path, contents).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_batch;
+
+    /// A batch with one survey collected, exported to a temp file.
+    fn exported(name: &str) -> (AppState, std::path::PathBuf) {
+        let mut s = test_batch();
+        s.collect(0, "Copper Ore".into(), None);
+        let path = std::env::temp_dir().join(format!("survey-{name}-{}.json", std::process::id()));
+        export_session(&s, &path, "json").unwrap();
+        (s, path)
+    }
+
+    #[test]
+    fn import_restores_the_trail_and_origin() {
+        let (before, path) = exported("import");
+        let mut after = AppState::default();
+        import_session(&mut after, &path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(after.survey_origin, before.survey_origin);
+        assert_eq!(after.player_pos, before.player_pos);
+        assert_eq!(after.trail, before.trail);
+        assert_eq!(after.distance_walked(), before.distance_walked());
+        assert_eq!(after.mode, Mode::Find);
+        assert_eq!(after.path_order, [1]);
+    }
+
+    fn archived(resource: &str, completed_at: u64) -> ArchivedBatch {
+        ArchivedBatch {
+            zone: "Serbule".into(),
+            survey_origin: (0.5, 0.5),
+            surveys: vec![Survey::new(resource.into(), 10, 20)],
+            route: vec![0],
+            started_at: None,
+            completed_at,
+            distance_walked_m: 0.0,
+            items_collected: vec![resource.into()],
+        }
+    }
+
+    #[test]
+    fn import_adds_to_the_local_archive() {
+        let mut theirs = AppState {
+            archive: vec![archived("Copper Ore", 1000), archived("Iron Ore", 3000)],
+            ..Default::default()
+        };
+        theirs.start_batch(&[Survey::new("Tin Ore".into(), -80, 20)]);
+        let path = std::env::temp_dir().join(format!("survey-archive-{}.json", std::process::id()));
+        export_session(&theirs, &path, "json").unwrap();
+
+        let mut ours = AppState {
+            archive: vec![archived("Copper Ore", 1000), archived("Gold Ore", 2000)],
+            ..Default::default()
+        };
+        ours.start_batch(&[Survey::new("Copper Ore".into(), 10, 20)]);
+        ours.remove_survey(0);
+        ours.group_starts.push((0.1, 0.1));
+
+        let before = ours.snapshot();
+        import_session(&mut ours, &path).unwrap();
+        ours.history.record(before);
+        let _ = std::fs::remove_file(&path);
+
+        let completed: Vec<u64> = ours.archive.iter().map(|b| b.completed_at).collect();
+        assert_eq!(completed, [1000, 2000, 3000]);
+        assert!(ours.removed.is_empty());
+        assert!(ours.group_starts.is_empty());
+
+        // Undoing the import takes back only what it added
+        ours.undo();
+        let completed: Vec<u64> = ours.archive.iter().map(|b| b.completed_at).collect();
+        assert_eq!(completed, [1000, 2000]);
+    }
+}
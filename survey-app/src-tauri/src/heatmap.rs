@@ -0,0 +1,203 @@
+use crate::estimator::SpawnPoint;
+use crate::survey::zone_dimensions;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// Smoothing radius (meters) of each spawn's contribution.
+const KERNEL_SIGMA: f64 = 25.0;
+const MIN_CELLS: usize = 4;
+const MAX_CELLS: usize = 200;
+
+/// Spawn counts smoothed with a Gaussian over a grid of square cells.
+/// Spawns are binned into cells before smoothing, so building one costs
+/// at most the grid's size however much history there is.
+#[derive(Debug, Clone)]
+pub struct DensityGrid {
+    pub cols: usize,
+    pub rows: usize,
+    /// Cell size in meters
+    pub cell_m: f64,
+    /// Row-major
+    pub cells: Vec<f64>,
+}
+
+impl DensityGrid {
+    /// `points` are in zone meters; ones off the grid count at its edge.
+    pub fn new(
+        points: impl IntoIterator<Item = (f64, f64)>,
+        cols: usize,
+        rows: usize,
+        cell_m: f64,
+        sigma: f64,
+    ) -> Self {
+        let bin = |v: f64, n: usize| ((v / cell_m).floor().max(0.0) as usize).min(n - 1);
+        let mut counts = vec![0u32; cols * rows];
+        for (x, y) in points {
+            if x.is_finite() && y.is_finite() {
+                counts[bin(y, rows) * cols + bin(x, cols)] += 1;
+            }
+        }
+
+        let reach = (3.0 * sigma / cell_m).ceil() as usize;
+        let mut cells = vec![0.0; cols * rows];
+        for (i, &count) in counts.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let (pr, pc) = (i / cols, i % cols);
+            for r in pr.saturating_sub(reach)..=(pr + reach).min(rows - 1) {
+                for c in pc.saturating_sub(reach)..=(pc + reach).min(cols - 1) {
+                    let d2 = ((c as f64 - pc as f64).powi(2) + (r as f64 - pr as f64).powi(2))
+                        * cell_m
+                        * cell_m;
+                    cells[r * cols + c] += count as f64 * (-d2 / (2.0 * sigma * sigma)).exp();
+                }
+            }
+        }
+        Self {
+            cols,
+            rows,
+            cell_m,
+            cells,
+        }
+    }
+
+    /// Smoothed count at a position in meters; 0 off the grid.
+    pub fn at(&self, x: f64, y: f64) -> f64 {
+        if x < 0.0 || y < 0.0 {
+            return 0.0;
+        }
+        let (c, r) = ((x / self.cell_m) as usize, (y / self.cell_m) as usize);
+        if c >= self.cols || r >= self.rows {
+            return 0.0;
+        }
+        self.cells[r * self.cols + c]
+    }
+}
+
+/// Spawn density over a zone, for drawing under the map overlay.
+#[derive(Debug, Clone, Serialize)]
+pub struct Heatmap {
+    pub zone: String,
+    /// `None` covers every resource
+    pub resource: Option<String>,
+    pub cols: usize,
+    pub rows: usize,
+    /// Row-major, scaled so the densest cell is 1.0
+    pub cells: Vec<f64>,
+    /// Number of spawns that went into the grid
+    pub samples: usize,
+    /// Center of the densest cell (0.0–1.0 relative); `None` with no samples
+    pub peak: Option<(f64, f64)>,
+    /// Resources with recorded spawns in this zone
+    pub resources: Vec<String>,
+}
+
+/// Bin the recorded spawns of `zone` (optionally one `resource`) into a
+/// grid `cols` cells wide, smoothing each with a Gaussian so sparse history
+/// still reads as areas rather than single cells.
+pub fn density_grid(
+    spawns: &[SpawnPoint],
+    zone: &str,
+    resource: Option<&str>,
+    cols: usize,
+) -> Heatmap {
+    let (zw, zh) = zone_dimensions(zone);
+    let (zw, zh) = (zw as f64, zh as f64);
+    let cols = cols.clamp(MIN_CELLS, MAX_CELLS);
+    let cell_m = zw / cols as f64;
+    let rows = ((zh / cell_m).ceil() as usize).max(1);
+
+    let in_zone: Vec<&SpawnPoint> = spawns.iter().filter(|p| p.zone == zone).collect();
+    let resources: BTreeSet<String> = in_zone.iter().map(|p| p.resource.clone()).collect();
+    let points: Vec<&SpawnPoint> = in_zone
+        .into_iter()
+        .filter(|p| resource.is_none_or(|r| p.resource == r))
+        .collect();
+
+    let sigma = KERNEL_SIGMA.max(cell_m / 2.0);
+    let mut cells =
+        DensityGrid::new(points.iter().map(|p| (p.x, p.y)), cols, rows, cell_m, sigma).cells;
+
+    let (peak_idx, peak_val) =
+        cells.iter().copied().enumerate().fold(
+            (0, 0.0),
+            |best, (i, v)| if v > best.1 { (i, v) } else { best },
+        );
+    let peak = (peak_val > 0.0).then(|| {
+        let (r, c) = (peak_idx / cols, peak_idx % cols);
+        (
+            (((c as f64 + 0.5) * cell_m) / zw).min(1.0),
+            (((r as f64 + 0.5) * cell_m) / zh).min(1.0),
+        )
+    });
+    if peak_val > 0.0 {
+        for v in &mut cells {
+            *v /= peak_val;
+        }
+    }
+
+    Heatmap {
+        zone: zone.to_string(),
+        resource: resource.map(str::to_string),
+        cols,
+        rows,
+        cells,
+        samples: points.len(),
+        peak,
+        resources: resources.into_iter().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(resource: &str, x: f64, y: f64) -> SpawnPoint {
+        SpawnPoint {
+            zone: "Serbule".into(),
+            resource: resource.into(),
+            x,
+            y,
+        }
+    }
+
+    #[test]
+    fn a_spawn_on_a_cell_boundary_counts_in_the_next_cell() {
+        let grid = DensityGrid::new([(10.0, 0.0), (9.999, 25.0)], 3, 3, 10.0, 1.0);
+        assert!((grid.at(15.0, 5.0) - 1.0).abs() < 1e-9);
+        assert!(grid.at(5.0, 5.0) < 1e-9);
+        assert!((grid.at(5.0, 25.0) - 1.0).abs() < 1e-9);
+
+        // Off the grid counts at its edge; looking off it finds nothing
+        let edge = DensityGrid::new([(-5.0, 100.0)], 3, 3, 10.0, 1.0);
+        assert!((edge.at(0.0, 29.0) - 1.0).abs() < 1e-9);
+        assert_eq!(edge.at(-1.0, 29.0), 0.0);
+        assert_eq!(edge.at(0.0, 30.0), 0.0);
+    }
+
+    #[test]
+    fn the_peak_is_the_middle_of_the_densest_cell() {
+        let (zw, zh) = zone_dimensions("Serbule");
+        let cell_m = zw as f64 / 4.0;
+        let spawns = [
+            spawn("Copper Ore", cell_m, cell_m),
+            spawn("Copper Ore", cell_m, cell_m),
+            spawn("Tin Ore", 3.5 * cell_m, 0.0),
+            SpawnPoint {
+                zone: "Eltibule".into(),
+                ..spawn("Iron Ore", 0.0, 0.0)
+            },
+        ];
+
+        let map = density_grid(&spawns, "Serbule", Some("Copper Ore"), 4);
+        assert_eq!((map.cols, map.samples), (4, 2));
+        assert_eq!(map.resources, ["Copper Ore", "Tin Ore"]);
+        let peak = map.peak.unwrap();
+        assert!((peak.0 - 1.5 * cell_m / zw as f64).abs() < 1e-9);
+        assert!((peak.1 - 1.5 * cell_m / zh as f64).abs() < 1e-9);
+        assert_eq!(map.cells[map.cols + 1], 1.0);
+
+        assert_eq!(density_grid(&[], "Serbule", None, 4).peak, None);
+    }
+}
@@ -0,0 +1,345 @@
+use crate::state::{ArchivedBatch, Mode, MotherlodeReading, QueuedReading, Removal, Survey};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::time::{Duration, Instant};
+
+/// How many steps back `undo` can go.
+const MAX_HISTORY: usize = 50;
+/// Repeats of a coalesced action within this window collapse into one undo step.
+const COALESCE_WINDOW: Duration = Duration::from_millis(1500);
+
+/// The session part of `AppState`: what undo/redo restores and what is
+/// saved across restarts. Settings, log position, map size and the archive
+/// of completed batches are not part of it; only the archive's length is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub mode: Mode,
+    pub zone: String,
+    pub surveys: Vec<Survey>,
+    pub player_pos: (f64, f64),
+    pub survey_origin: (f64, f64),
+    pub trail: Vec<(f64, f64)>,
+    pub batch_size: usize,
+    pub path_order: Vec<usize>,
+    pub next_target: Option<usize>,
+    pub record_buffer: Vec<Survey>,
+    pub batch_started_at: Option<u64>,
+    pub collected_items: Vec<String>,
+    pub queued: Vec<QueuedReading>,
+    pub removed: Vec<Removal>,
+    pub motherlode_readings: Vec<MotherlodeReading>,
+    pub group_starts: Vec<(f64, f64)>,
+    pub member_routes: Vec<Vec<usize>>,
+    /// How many batches the archive held
+    pub archived: usize,
+}
+
+/// Bounded undo/redo stacks of session snapshots.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// Batches taken back out of the archive by undo, newest last, for redo
+    reopened: Vec<ArchivedBatch>,
+    #[serde(skip)]
+    last_action: Option<(&'static str, Instant)>,
+}
+
+impl History {
+    /// Remember `before` as the state prior to a mutation. Clears the redo stack.
+    pub fn record(&mut self, before: Snapshot) {
+        self.last_action = None;
+        self.push(before);
+    }
+
+    /// Like `record`, but repeats of `action` in quick succession (the
+    /// pointer moves of one drag) share a single undo step.
+    pub fn record_coalesced(&mut self, before: Snapshot, action: &'static str) {
+        let now = Instant::now();
+        let repeat = matches!(
+            self.last_action,
+            Some((last, at)) if last == action && now.duration_since(at) < COALESCE_WINDOW
+        );
+        self.last_action = Some((action, now));
+        if !repeat {
+            self.push(before);
+        }
+    }
+
+    fn push(&mut self, before: Snapshot) {
+        self.undo.push_back(before);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+        self.reopened.clear();
+    }
+
+    /// Step back: returns the snapshot to restore, keeping `current` for redo.
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let previous = self.undo.pop_back()?;
+        self.redo.push(current);
+        self.last_action = None;
+        Some(previous)
+    }
+
+    /// Step forward again after an undo.
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let next = self.redo.pop()?;
+        self.undo.push_back(current);
+        self.last_action = None;
+        Some(next)
+    }
+
+    /// Hold a batch an undo took out of the archive until it's redone.
+    pub fn reopen(&mut self, batch: ArchivedBatch) {
+        self.reopened.push(batch);
+    }
+
+    /// The batch the latest undo took out of the archive.
+    pub fn rearchive(&mut self) -> Option<ArchivedBatch> {
+        self.reopened.pop()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+/// How long changes have to settle before the session is written.
+const SAVE_DELAY: Duration = Duration::from_millis(500);
+
+/// Writes the session on a background thread once a burst of changes (a
+/// drag, a run of log lines) settles, rather than on every change.
+pub struct SessionSaver {
+    tx: Sender<()>,
+}
+
+impl SessionSaver {
+    pub fn start(save: impl Fn() + Send + 'static) -> Self {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            while rx.recv().is_ok() {
+                while rx.recv_timeout(SAVE_DELAY).is_ok() {}
+                save();
+            }
+        });
+        Self { tx }
+    }
+
+    /// Save soon; requests less than `SAVE_DELAY` apart share one write.
+    pub fn request(&self) {
+        let _ = self.tx.send(());
+    }
+}
+
+/// How far the chat log has been read.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogPosition {
+    pub file: PathBuf,
+    /// Byte offset of the first unread line
+    pub position: u64,
+}
+
+/// On-disk form of a session: the live snapshot plus its history.
+#[derive(Serialize, Deserialize)]
+pub struct SavedSession {
+    pub snapshot: Snapshot,
+    pub archive: Vec<ArchivedBatch>,
+    pub history: History,
+    /// `None` before any log has been read
+    pub log_position: Option<LogPosition>,
+}
+
+impl SavedSession {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&json).map_err(|e| format!("Not a session file: {e}"))
+    }
+}
+
+/// Write a session without cloning the history.
+pub fn save_session(
+    path: &Path,
+    snapshot: &Snapshot,
+    archive: &[ArchivedBatch],
+    history: &History,
+    log_position: Option<&LogPosition>,
+) -> std::io::Result<()> {
+    #[derive(Serialize)]
+    struct SavedSessionRef<'a> {
+        snapshot: &'a Snapshot,
+        archive: &'a [ArchivedBatch],
+        history: &'a History,
+        log_position: Option<&'a LogPosition>,
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string(&SavedSessionRef {
+        snapshot,
+        archive,
+        history,
+        log_position,
+    })?;
+    std::fs::write(path, json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{test_batch, AppState, SurveyStatus};
+
+    #[test]
+    fn undo_and_redo_step_through_changes() {
+        let mut s = test_batch();
+        assert!(!s.undo());
+
+        s.checkpoint();
+        s.toggle_found(0);
+        s.checkpoint();
+        s.zone = "Eltibule".into();
+
+        assert!(s.undo());
+        assert_eq!(s.zone, "Serbule");
+        assert!(s.undo());
+        assert_eq!(s.surveys[0].status, SurveyStatus::Pending);
+        assert!(!s.history.can_undo());
+
+        assert!(s.redo());
+        assert_eq!(s.surveys[0].status, SurveyStatus::Found);
+        assert!(s.redo());
+        assert_eq!(s.zone, "Eltibule");
+        assert!(!s.redo());
+    }
+
+    #[test]
+    fn a_new_change_drops_the_redo_steps() {
+        let mut s = test_batch();
+        s.checkpoint();
+        s.toggle_found(0);
+        s.undo();
+        assert!(s.history.can_redo());
+        s.checkpoint();
+        s.toggle_found(1);
+        assert!(!s.history.can_redo());
+    }
+
+    #[test]
+    fn a_drag_is_one_step() {
+        let mut s = test_batch();
+        for x in [0.1, 0.2, 0.3] {
+            s.checkpoint_drag("move player");
+            s.player_pos = (x, 0.5);
+        }
+        s.undo();
+        assert_eq!(s.player_pos, (0.5, 0.5));
+        assert!(!s.history.can_undo());
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut s = test_batch();
+        for _ in 0..MAX_HISTORY + 10 {
+            s.checkpoint();
+        }
+        let mut steps = 0;
+        while s.undo() {
+            steps += 1;
+        }
+        assert_eq!(steps, MAX_HISTORY);
+    }
+
+    #[test]
+    fn archiving_a_batch_can_be_undone_and_redone() {
+        let mut s = test_batch();
+        s.checkpoint();
+        s.toggle_found(0);
+        s.checkpoint();
+        s.toggle_found(1);
+        assert!(s.complete_batch().is_some());
+        assert!(s.surveys.is_empty());
+
+        assert!(s.undo());
+        assert!(s.archive.is_empty());
+        assert_eq!(s.surveys.len(), 2);
+        assert!(s.surveys[1].is_pending());
+
+        assert!(s.redo());
+        assert_eq!(s.archive.len(), 1);
+        assert!(s.surveys.is_empty());
+
+        // Re-opening and changing the batch lets it be archived afresh
+        s.undo();
+        s.checkpoint();
+        s.toggle_found(1);
+        assert!(s.complete_batch().is_some());
+        assert_eq!(s.archive.len(), 1);
+        assert!(s.undo());
+        assert!(s.archive.is_empty());
+    }
+
+    #[test]
+    fn sessions_keep_the_archive_outside_the_snapshot() {
+        let path = std::env::temp_dir().join(format!("survey-session-{}.json", std::process::id()));
+        let mut s = test_batch();
+        s.session_path = Some(path.clone());
+        s.toggle_found(0);
+        s.toggle_found(1);
+        s.complete_batch();
+        s.save_session().unwrap();
+
+        let saved: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["archive"].as_array().unwrap().len(), 1);
+        assert!(saved["snapshot"].get("archive").is_none());
+
+        let mut loaded = AppState::default();
+        loaded.load_session(path.clone());
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.archive.len(), 1);
+    }
+
+    #[test]
+    fn unreadable_sessions_are_moved_aside() {
+        let path = std::env::temp_dir().join(format!("survey-bad-{}.json", std::process::id()));
+        let backup = path.with_extension("json.bak");
+        std::fs::write(&path, "{ not a session").unwrap();
+
+        let mut s = AppState::default();
+        s.load_session(path.clone());
+        s.save_session().unwrap();
+        let kept = std::fs::read_to_string(&backup);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&backup);
+        assert_eq!(kept.unwrap(), "{ not a session");
+    }
+
+    #[test]
+    fn saves_wait_for_changes_to_settle() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let saves = Arc::new(AtomicUsize::new(0));
+        let saver = SessionSaver::start({
+            let saves = saves.clone();
+            move || {
+                saves.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        for _ in 0..10 {
+            saver.request();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(saves.load(Ordering::SeqCst), 0);
+        std::thread::sleep(SAVE_DELAY * 3);
+        assert_eq!(saves.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,386 @@
+use crate::state::now_ms;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::time::{Duration, Instant};
+
+/// Something the chat log watcher noticed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    /// A batch was read and the route is ready
+    BatchRecorded,
+    SurveyCollected,
+    /// Every survey in the batch was dealt with
+    BatchComplete,
+    ZoneChanged,
+}
+
+/// What a hook does with the event's JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HookAction {
+    /// Run through the system shell with the JSON on stdin
+    Command { command: String },
+    /// POST the JSON to an http:// URL
+    Post { url: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hook {
+    pub event: HookEvent,
+    pub action: HookAction,
+    /// Give up (and kill a command) after this long
+    #[serde(default = "default_timeout")]
+    pub timeout_ms: u64,
+}
+
+fn default_timeout() -> u64 {
+    5000
+}
+
+/// Longest a hook may run when tried out from the settings.
+pub const MAX_TIMEOUT_MS: u64 = 30_000;
+
+/// How much of a failed command's stderr makes it into the error.
+const MAX_STDERR: u64 = 4096;
+
+/// An event waiting to be sent to hooks, with its details as `data`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Fired {
+    pub event: HookEvent,
+    /// Unix time (ms)
+    pub at: u64,
+    pub zone: String,
+    pub data: serde_json::Value,
+}
+
+impl Fired {
+    pub fn new(event: HookEvent, zone: &str, data: serde_json::Value) -> Self {
+        Self {
+            event,
+            at: now_ms(),
+            zone: zone.to_string(),
+            data,
+        }
+    }
+}
+
+/// Reject hooks that could never run.
+pub fn validate(hook: &Hook) -> Result<(), String> {
+    match &hook.action {
+        HookAction::Command { command } if command.trim().is_empty() => {
+            Err("A command hook needs a command".into())
+        }
+        HookAction::Post { url } if !url.starts_with("http://") => {
+            Err(format!("Only http:// URLs can be posted to: {url}"))
+        }
+        _ if hook.timeout_ms == 0 => Err("A hook's timeout must be above zero".into()),
+        _ => Ok(()),
+    }
+}
+
+/// Events a hook's worker can fall behind by before new ones are dropped.
+const QUEUE: usize = 32;
+
+struct Worker {
+    hook: Hook,
+    queue: SyncSender<Vec<u8>>,
+}
+
+/// Runs hooks in the background so the watcher never waits on them. Each
+/// hook has its own worker, so it sees events in the order they happened
+/// and at most one of its jobs runs at a time. Failures are printed and,
+/// with a `log` path, appended there.
+#[derive(Default)]
+pub struct HookRunner {
+    workers: Vec<Worker>,
+    log: Option<PathBuf>,
+}
+
+impl HookRunner {
+    /// Workers stop once the runner is dropped and their queue is empty.
+    pub fn start(hooks: &[Hook], log: Option<PathBuf>) -> Self {
+        let workers = hooks
+            .iter()
+            .map(|hook| {
+                let (queue, jobs) = mpsc::sync_channel::<Vec<u8>>(QUEUE);
+                let worker_hook = hook.clone();
+                let log = log.clone();
+                std::thread::spawn(move || {
+                    for payload in jobs {
+                        if let Err(e) = run(&worker_hook, &payload) {
+                            log_failure(&worker_hook, &e, log.as_deref());
+                        }
+                    }
+                });
+                Worker {
+                    hook: hook.clone(),
+                    queue,
+                }
+            })
+            .collect();
+        Self { workers, log }
+    }
+
+    /// Queue each event for the hooks listening for it.
+    pub fn fire(&self, events: Vec<Fired>) {
+        for fired in events {
+            let listening: Vec<&Worker> = self
+                .workers
+                .iter()
+                .filter(|w| w.hook.event == fired.event)
+                .collect();
+            if listening.is_empty() {
+                continue;
+            }
+            let payload = serde_json::to_vec(&fired).unwrap_or_default();
+            for worker in listening {
+                if let Err(TrySendError::Full(_)) = worker.queue.try_send(payload.clone()) {
+                    let error = "too far behind, event dropped";
+                    log_failure(&worker.hook, error, self.log.as_deref());
+                }
+            }
+        }
+    }
+}
+
+fn log_failure(hook: &Hook, error: &str, log: Option<&Path>) {
+    let target = match &hook.action {
+        HookAction::Command { command } => command,
+        HookAction::Post { url } => url,
+    };
+    let line = format!("{:?} hook `{target}` failed: {error}", hook.event);
+    eprintln!("{line}");
+    if let Some(path) = log {
+        let written = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{} {line}", now_ms()));
+        if let Err(e) = written {
+            eprintln!("Failed to write hook log: {e}");
+        }
+    }
+}
+
+/// Run one hook with `payload`, waiting for it to finish.
+pub fn run(hook: &Hook, payload: &[u8]) -> Result<(), String> {
+    let timeout = Duration::from_millis(hook.timeout_ms);
+    match &hook.action {
+        HookAction::Command { command } => run_command(command, payload, timeout),
+        HookAction::Post { url } => post(url, payload, timeout),
+    }
+}
+
+fn shell(command: &str) -> Command {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command).creation_flags(CREATE_NO_WINDOW);
+        cmd
+    }
+    #[cfg(not(windows))]
+    {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+fn run_command(command: &str, payload: &[u8], timeout: Duration) -> Result<(), String> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("couldn't start: {e}"))?;
+
+    // Write from another thread so a command that ignores stdin can't stall us
+    if let Some(mut stdin) = child.stdin.take() {
+        let payload = payload.to_vec();
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(&payload);
+        });
+    }
+    // Drain stderr as it comes so a chatty command can't fill the pipe and stall
+    let stderr = child.stderr.take().map(|mut pipe| {
+        std::thread::spawn(move || {
+            let mut kept = Vec::new();
+            let _ = (&mut pipe).take(MAX_STDERR).read_to_end(&mut kept);
+            let _ = std::io::copy(&mut pipe, &mut std::io::sink());
+            String::from_utf8_lossy(&kept).into_owned()
+        })
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) => break status,
+            None if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {}ms", timeout.as_millis()));
+            }
+            None => std::thread::sleep(Duration::from_millis(20)),
+        }
+    };
+    if status.success() {
+        return Ok(());
+    }
+    let stderr = stderr
+        .and_then(|reader| reader.join().ok())
+        .unwrap_or_default();
+    match stderr.trim() {
+        "" => Err(format!("failed ({status})")),
+        stderr => Err(format!("failed ({status}): {stderr}")),
+    }
+}
+
+fn post(url: &str, payload: &[u8], timeout: Duration) -> Result<(), String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or("only http:// URLs are supported")?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let addr = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{host}:80")
+    };
+    let addr = addr
+        .to_socket_addrs()
+        .map_err(|e| format!("bad address {host}: {e}"))?
+        .next()
+        .ok_or_else(|| format!("bad address {host}"))?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, timeout).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+        .map_err(|e| e.to_string())?;
+    let head = format!(
+        "POST {path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        payload.len()
+    );
+    stream
+        .write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(payload))
+        .map_err(|e| e.to_string())?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut status_line)
+        .map_err(|e| format!("no response: {e}"))?;
+    let status_line = status_line.trim();
+    match status_line.split(' ').nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        Some(_) => Err(format!("answered {status_line}")),
+        None => Err("no response".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn hook(action: HookAction) -> Hook {
+        Hook {
+            event: HookEvent::SurveyCollected,
+            action,
+            timeout_ms: default_timeout(),
+        }
+    }
+
+    fn command(command: &str) -> Hook {
+        hook(HookAction::Command {
+            command: command.into(),
+        })
+    }
+
+    #[test]
+    fn validate_rejects_unusable_hooks() {
+        assert!(validate(&command("notify-send hi")).is_ok());
+        assert!(validate(&command("  ")).is_err());
+        assert!(validate(&hook(HookAction::Post {
+            url: "http://localhost:9000/hook".into()
+        }))
+        .is_ok());
+        assert!(validate(&hook(HookAction::Post {
+            url: "https://example.com".into()
+        }))
+        .is_err());
+        let mut instant = command("true");
+        instant.timeout_ms = 0;
+        assert!(validate(&instant).is_err());
+    }
+
+    #[test]
+    fn hooks_default_their_timeout() {
+        let hook: Hook = serde_json::from_value(json!({
+            "event": "batch_complete",
+            "action": { "kind": "post", "url": "http://localhost/" },
+        }))
+        .unwrap();
+        assert_eq!(hook.event, HookEvent::BatchComplete);
+        assert_eq!(hook.timeout_ms, 5000);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_hook_sees_events_in_order() {
+        let out = std::env::temp_dir().join(format!("survey-hooks-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&out);
+        // Each run appends its event's index; a slow first run must not be overtaken
+        let script = format!(
+            r#"read -r line; case "$line" in *'"index":0'*) sleep 0.3;; esac; echo "$line" | sed 's/.*"index":\([0-9]*\).*/\1/' >> {}"#,
+            out.display()
+        );
+        let runner = HookRunner::start(&[command(&script)], None);
+        let events = (0..4)
+            .map(|i| Fired::new(HookEvent::SurveyCollected, "Serbule", json!({ "index": i })))
+            .chain([Fired::new(HookEvent::ZoneChanged, "Eltibule", json!({}))])
+            .collect();
+        runner.fire(events);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let written = loop {
+            let written = std::fs::read_to_string(&out).unwrap_or_default();
+            if written.lines().count() >= 4 || Instant::now() > deadline {
+                break written;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        };
+        let _ = std::fs::remove_file(&out);
+        assert_eq!(written.lines().collect::<Vec<_>>(), ["0", "1", "2", "3"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failures_are_logged() {
+        let log = std::env::temp_dir().join(format!("survey-hooks-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        let mut slow = command("sleep 5");
+        slow.timeout_ms = 100;
+        assert_eq!(run(&slow, b"{}"), Err("timed out after 100ms".into()));
+        let failing = command("echo oops >&2; exit 3");
+        let error = run(&failing, b"{}").unwrap_err();
+        assert!(error.ends_with(": oops"), "{error}");
+        let chatty = command("yes oops | head -c 1000000 >&2; exit 3");
+        let error = run(&chatty, b"{}").unwrap_err();
+        assert!(error.starts_with("failed"), "{error}");
+
+        log_failure(&failing, &error, Some(&log));
+        let written = std::fs::read_to_string(&log).unwrap();
+        let _ = std::fs::remove_file(&log);
+        assert!(written.contains("SurveyCollected hook `echo oops >&2; exit 3` failed"));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::state::{Mode, SurveyStatus};
+use crate::SharedState;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutEvent, ShortcutState};
+
+/// What a global shortcut does; all act on the committed next target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HotkeyAction {
+    ToggleFound,
+    Skip,
+    ToggleMode,
+    Clear,
+}
+
+impl HotkeyAction {
+    fn name(self) -> &'static str {
+        match self {
+            HotkeyAction::ToggleFound => "mark found",
+            HotkeyAction::Skip => "skip",
+            HotkeyAction::ToggleMode => "switch mode",
+            HotkeyAction::Clear => "clear",
+        }
+    }
+}
+
+/// Accelerator strings per action, e.g. "Ctrl+Alt+F". Empty by default:
+/// global shortcuts fire in every program (and Ctrl+Alt is AltGr on many
+/// layouts), so none are taken until the user sets them.
+pub type Bindings = BTreeMap<HotkeyAction, String>;
+
+/// Parse every binding, rejecting ones that don't parse or that two
+/// actions share (however the modifiers are spelled or ordered).
+fn parse_bindings(bindings: &Bindings) -> Result<Vec<(HotkeyAction, Shortcut)>, String> {
+    let mut parsed: Vec<(HotkeyAction, Shortcut)> = Vec::new();
+    for (&action, accelerator) in bindings {
+        let shortcut = Shortcut::from_str(accelerator)
+            .map_err(|e| format!("Can't use \"{accelerator}\" to {}: {e}", action.name()))?;
+        if let Some((other, _)) = parsed.iter().find(|(_, s)| s.id() == shortcut.id()) {
+            return Err(format!(
+                "{accelerator} is bound to both {} and {}",
+                other.name(),
+                action.name()
+            ));
+        }
+        parsed.push((action, shortcut));
+    }
+    Ok(parsed)
+}
+
+/// Replace the registered shortcuts with `bindings`. Shortcuts another
+/// program already holds are reported; the rest stay registered.
+pub fn register(app: &AppHandle, bindings: &Bindings) -> Result<(), String> {
+    let parsed = parse_bindings(bindings)?;
+    let shortcuts = app.global_shortcut();
+    shortcuts.unregister_all().map_err(|e| e.to_string())?;
+
+    let taken: Vec<String> = parsed
+        .into_iter()
+        .filter_map(|(action, shortcut)| {
+            shortcuts
+                .register(shortcut)
+                .err()
+                .map(|_| format!("{} ({})", bindings[&action], action.name()))
+        })
+        .collect();
+    if taken.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Already in use by another program: {}",
+            taken.join(", ")
+        ))
+    }
+}
+
+/// Global shortcut handler: run the bound action like the matching command.
+pub fn on_shortcut(app: &AppHandle, shortcut: &Shortcut, event: ShortcutEvent) {
+    if event.state() != ShortcutState::Pressed {
+        return;
+    }
+    let state = app.state::<SharedState>();
+    let mut s = state.lock().unwrap();
+    let Ok(parsed) = parse_bindings(&s.settings.hotkeys) else {
+        return;
+    };
+    let Some(&(action, _)) = parsed.iter().find(|(_, sc)| sc.id() == shortcut.id()) else {
+        return;
+    };
+
+    match action {
+        HotkeyAction::ToggleFound | HotkeyAction::Skip => {
+            let Some(index) = s.next_target else {
+                return;
+            };
+            s.checkpoint();
+            if action == HotkeyAction::Skip {
+                s.surveys[index].set_status(SurveyStatus::Skipped);
+                s.reroute();
+            } else {
+                s.toggle_found(index);
+            }
+            crate::emit_completed(app, s.complete_batch());
+        }
+        HotkeyAction::ToggleMode => {
+            s.checkpoint();
+            let mode = match s.mode {
+                Mode::Record => Mode::Find,
+                Mode::Find => Mode::Record,
+            };
+            s.switch_mode(mode);
+        }
+        HotkeyAction::Clear => {
+            s.checkpoint();
+            s.clear_batch();
+        }
+    }
+    crate::commit(app, &s);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+
+    #[test]
+    fn nothing_is_bound_by_default() {
+        assert!(Settings::default().hotkeys.is_empty());
+        let saved: Settings = serde_json::from_str(r#"{ "reroute_margin": 0.2 }"#).unwrap();
+        assert!(saved.hotkeys.is_empty());
+    }
+
+    #[test]
+    fn shared_or_bad_shortcuts_are_refused() {
+        let mut bindings = Bindings::from([
+            (HotkeyAction::ToggleFound, "Ctrl+Shift+F1".to_string()),
+            (HotkeyAction::Skip, "Ctrl+Shift+F2".to_string()),
+        ]);
+        assert_eq!(parse_bindings(&bindings).unwrap().len(), 2);
+
+        bindings.insert(HotkeyAction::Clear, "shift+control+F1".into());
+        let err = parse_bindings(&bindings).unwrap_err();
+        assert!(err.contains("mark found") && err.contains("clear"), "{err}");
+
+        bindings.insert(HotkeyAction::Clear, "Ctrl+Nope".into());
+        assert!(parse_bindings(&bindings).is_err());
+    }
+}
@@ -1,15 +1,74 @@
+mod chat;
+mod cli;
+mod directions;
+mod estimator;
+mod export;
+mod heatmap;
+mod history;
+mod hooks;
+mod hotkeys;
+mod overlay;
 mod pathfinder;
+mod render;
+mod server;
+mod settings;
+mod share;
 mod state;
+mod storage;
 mod survey;
+mod sync;
 mod watcher;
 
-use state::{AppState, Mode, RenderPayload};
+use chat::ImportReport;
+use directions::Leg;
+use heatmap::Heatmap;
+use history::SessionSaver;
+use hooks::{Fired, Hook};
+use hotkeys::{Bindings, HotkeyAction};
+use serde::{Deserialize, Serialize};
+use server::{LiveServer, ServerSettings};
+use settings::Settings;
+use state::{AppState, ArchivedBatch, Mode, RenderPayload, Survey, SurveyStatus};
 use std::sync::{Arc, Mutex};
-use survey::compute_render_payload;
-use tauri::{Manager, State};
+use storage::Total;
+use survey::{compute_render_payload, validate_offset, validate_resource, zone_dimensions};
+use sync::{SyncBatch, SyncHub, SyncSettings};
+use tauri::{AppHandle, Emitter, Listener, Manager, State, WindowEvent};
 use watcher::FileWatcher;
 
 type SharedState = Arc<Mutex<AppState>>;
+type ServerState = Mutex<Option<LiveServer>>;
+type SyncState = Mutex<Option<SyncHub>>;
+
+/// Announce batches archived by `AppState::complete_batch`. Peers get the
+/// final statuses here; by `commit` the batch is gone.
+fn emit_completed(app: &AppHandle, batch: Option<ArchivedBatch>) {
+    if let Some(batch) = batch {
+        if let Some(hub) = app.state::<SyncState>().lock().unwrap().as_ref() {
+            hub.publish(SyncBatch::from_archived(&batch));
+        }
+        let _ = app.emit("batch-completed", batch);
+    }
+}
+
+fn save_session(state: &SharedState) {
+    if let Err(e) = state.lock().unwrap().save_session() {
+        eprintln!("Failed to save session: {e}");
+    }
+}
+
+/// Persist the session after a mutation and build the payload for the
+/// frontend, telling every window (e.g. the overlay) and sync peer about
+/// the new state.
+fn commit(app: &AppHandle, s: &AppState) -> RenderPayload {
+    app.state::<SessionSaver>().request();
+    if let Some(hub) = app.state::<SyncState>().lock().unwrap().as_ref() {
+        hub.publish(SyncBatch::from_state(s));
+    }
+    let payload = compute_render_payload(s);
+    let _ = app.emit("state-updated", &payload);
+    payload
+}
 
 #[tauri::command]
 fn get_render_state(state: State<'_, SharedState>) -> RenderPayload {
@@ -18,35 +77,123 @@ fn get_render_state(state: State<'_, SharedState>) -> RenderPayload {
 }
 
 #[tauri::command]
-fn set_mode(mode: String, state: State<'_, SharedState>) -> RenderPayload {
+fn set_mode(mode: String, app: AppHandle, state: State<'_, SharedState>) -> RenderPayload {
     let mut s = state.lock().unwrap();
-    s.mode = match mode.as_str() {
+    s.checkpoint();
+    s.switch_mode(match mode.as_str() {
         "find" => Mode::Find,
         _ => Mode::Record,
-    };
-    if s.mode == Mode::Find {
-        s.path_order = pathfinder::find_path(s.player_pos, &s.surveys, &s.zone);
-    }
-    compute_render_payload(&s)
+    });
+    commit(&app, &s)
 }
 
 #[tauri::command]
-fn set_batch_size(n: usize, state: State<'_, SharedState>) -> RenderPayload {
+fn set_batch_size(n: usize, app: AppHandle, state: State<'_, SharedState>) -> RenderPayload {
     let mut s = state.lock().unwrap();
+    s.checkpoint();
     s.batch_size = n.max(1);
-    compute_render_payload(&s)
+    commit(&app, &s)
+}
+
+/// Stop recording now and search for whatever has been buffered so far.
+#[tauri::command]
+fn finish_batch(app: AppHandle, state: State<'_, SharedState>) -> Result<RenderPayload, String> {
+    let mut s = state.lock().unwrap();
+    if s.record_buffer.is_empty() {
+        return Err("No surveys recorded yet".into());
+    }
+    s.checkpoint();
+    let buffered = std::mem::take(&mut s.record_buffer);
+    s.start_batch(&buffered);
+    Ok(commit(&app, &s))
 }
 
+/// Drop the most recent reading from the record buffer.
 #[tauri::command]
-fn set_player_pos(x: f64, y: f64, state: State<'_, SharedState>) -> RenderPayload {
+fn discard_last_reading(
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> Result<RenderPayload, String> {
     let mut s = state.lock().unwrap();
+    if s.record_buffer.is_empty() {
+        return Err("No surveys recorded yet".into());
+    }
+    s.checkpoint();
+    s.record_buffer.pop();
+    Ok(commit(&app, &s))
+}
+
+/// Grow the batch while recording; buffered readings are kept.
+#[tauri::command]
+fn extend_batch(by: usize, app: AppHandle, state: State<'_, SharedState>) -> RenderPayload {
+    let mut s = state.lock().unwrap();
+    s.checkpoint();
+    s.batch_size += by.max(1);
+    commit(&app, &s)
+}
+
+#[tauri::command]
+fn set_player_pos(x: f64, y: f64, app: AppHandle, state: State<'_, SharedState>) -> RenderPayload {
+    let mut s = state.lock().unwrap();
+    s.checkpoint_drag("move player");
     s.player_pos = (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0));
+    // Until the first collection the player is still at the survey spot
+    if s.trail.is_empty() {
+        s.survey_origin = s.player_pos;
+    }
+    if s.mode == Mode::Find {
+        s.reroute();
+    }
+    commit(&app, &s)
+}
+
+/// Re-anchor the surveyed dots without moving the player.
+#[tauri::command]
+fn set_survey_origin(
+    x: f64,
+    y: f64,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> RenderPayload {
+    let mut s = state.lock().unwrap();
+    s.checkpoint_drag("move survey origin");
+    s.survey_origin = (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0));
     if s.mode == Mode::Find {
-        s.path_order = pathfinder::find_path(s.player_pos, &s.surveys, &s.zone);
+        s.reroute();
     }
+    commit(&app, &s)
+}
+
+#[tauri::command]
+fn estimate_survey_origin(state: State<'_, SharedState>) -> RenderPayload {
+    let mut s = state.lock().unwrap();
+    s.estimate_position();
     compute_render_payload(&s)
 }
 
+/// Move the survey origin to the estimated position. The player moves with
+/// it if they haven't collected anything yet.
+#[tauri::command]
+fn accept_position_estimate(
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> Result<RenderPayload, String> {
+    let mut s = state.lock().unwrap();
+    let estimate = s
+        .position_estimate
+        .take()
+        .ok_or("No position estimate available")?;
+    s.checkpoint();
+    s.survey_origin = estimate.pos;
+    if s.trail.is_empty() {
+        s.player_pos = estimate.pos;
+    }
+    if s.mode == Mode::Find {
+        s.reroute();
+    }
+    Ok(commit(&app, &s))
+}
+
 #[tauri::command]
 fn set_map_size(w: f64, h: f64, state: State<'_, SharedState>) -> RenderPayload {
     let mut s = state.lock().unwrap();
@@ -56,33 +203,621 @@ fn set_map_size(w: f64, h: f64, state: State<'_, SharedState>) -> RenderPayload
 }
 
 #[tauri::command]
-fn set_zone(zone: String, state: State<'_, SharedState>) -> RenderPayload {
+fn set_zone(zone: String, app: AppHandle, state: State<'_, SharedState>) -> RenderPayload {
     let mut s = state.lock().unwrap();
+    s.checkpoint();
     s.zone = zone;
     if s.mode == Mode::Find {
-        s.path_order = pathfinder::find_path(s.player_pos, &s.surveys, &s.zone);
+        s.reroute();
     }
-    compute_render_payload(&s)
+    commit(&app, &s)
 }
 
 #[tauri::command]
-fn toggle_found(index: usize, state: State<'_, SharedState>) -> RenderPayload {
+fn toggle_found(
+    index: usize,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> Result<RenderPayload, String> {
     let mut s = state.lock().unwrap();
-    if index < s.surveys.len() {
-        s.surveys[index].found = !s.surveys[index].found;
-        s.path_order = pathfinder::find_path(s.player_pos, &s.surveys, &s.zone);
+    if index >= s.surveys.len() {
+        return Err("No such survey".into());
     }
-    compute_render_payload(&s)
+    s.checkpoint();
+    s.toggle_found(index);
+    emit_completed(&app, s.complete_batch());
+    Ok(commit(&app, &s))
 }
 
+/// Set a survey's status directly, e.g. to skip a node or mark it unreachable
+/// without pretending it was collected.
 #[tauri::command]
-fn clear_surveys(state: State<'_, SharedState>) -> RenderPayload {
+fn set_survey_status(
+    index: usize,
+    status: SurveyStatus,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> Result<RenderPayload, String> {
     let mut s = state.lock().unwrap();
-    s.surveys.clear();
-    s.record_buffer.clear();
-    s.path_order.clear();
-    s.mode = Mode::Record;
-    compute_render_payload(&s)
+    if index >= s.surveys.len() {
+        return Err("No such survey".into());
+    }
+    s.checkpoint();
+    s.surveys[index].set_status(status);
+    s.reroute();
+    emit_completed(&app, s.complete_batch());
+    Ok(commit(&app, &s))
+}
+
+#[tauri::command]
+fn add_group_member(
+    x: f64,
+    y: f64,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> RenderPayload {
+    let mut s = state.lock().unwrap();
+    s.checkpoint();
+    s.group_starts.push((x.clamp(0.0, 1.0), y.clamp(0.0, 1.0)));
+    if s.mode == Mode::Find {
+        s.reroute();
+    }
+    commit(&app, &s)
+}
+
+/// Move another group member's start. `member` counts from 1; member 0 is
+/// the local player, moved with `set_player_pos`.
+#[tauri::command]
+fn set_group_member_pos(
+    member: usize,
+    x: f64,
+    y: f64,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> Result<RenderPayload, String> {
+    let mut s = state.lock().unwrap();
+    let i = member
+        .checked_sub(1)
+        .filter(|&i| i < s.group_starts.len())
+        .ok_or("No such group member")?;
+    s.checkpoint_drag("move group member");
+    s.group_starts[i] = (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0));
+    if s.mode == Mode::Find {
+        s.reroute();
+    }
+    Ok(commit(&app, &s))
+}
+
+#[tauri::command]
+fn remove_group_member(
+    member: usize,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> Result<RenderPayload, String> {
+    let mut s = state.lock().unwrap();
+    let i = member
+        .checked_sub(1)
+        .filter(|&i| i < s.group_starts.len())
+        .ok_or("No such group member")?;
+    s.checkpoint();
+    s.group_starts.remove(i);
+    if s.mode == Mode::Find {
+        s.reroute();
+    }
+    Ok(commit(&app, &s))
+}
+
+#[tauri::command]
+fn toggle_resource(
+    name: String,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> Result<RenderPayload, String> {
+    let mut s = state.lock().unwrap();
+    if !s.settings.excluded_resources.remove(&name) {
+        s.settings.excluded_resources.insert(name);
+    }
+    s.save_settings().map_err(|e| e.to_string())?;
+    if s.mode == Mode::Find {
+        s.reroute();
+    }
+    // Excluding the last pending resource finishes the batch, undoably
+    let before = s.snapshot();
+    let completed = s.complete_batch();
+    if completed.is_some() {
+        s.history.record(before);
+    }
+    emit_completed(&app, completed);
+    Ok(commit(&app, &s))
+}
+
+#[tauri::command]
+fn set_reroute_margin(margin: f64, state: State<'_, SharedState>) -> Result<RenderPayload, String> {
+    let mut s = state.lock().unwrap();
+    s.settings.reroute_margin = margin.clamp(0.0, 0.9);
+    s.save_settings().map_err(|e| e.to_string())?;
+    Ok(compute_render_payload(&s))
+}
+
+#[tauri::command]
+fn clear_surveys(app: AppHandle, state: State<'_, SharedState>) -> RenderPayload {
+    let mut s = state.lock().unwrap();
+    s.checkpoint();
+    s.clear_batch();
+    commit(&app, &s)
+}
+
+/// Where a hand-entered survey is: an offset from the survey origin as the
+/// game reports it, or a point on the map (0.0–1.0 relative).
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Placement {
+    Offset { dx: i32, dy: i32 },
+    Point { x: f64, y: f64 },
+}
+
+fn placement_offset(placement: Placement, s: &AppState) -> Result<(i32, i32), String> {
+    let (dx, dy) = match placement {
+        Placement::Offset { dx, dy } => (dx, dy),
+        Placement::Point { x, y } => {
+            if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+                return Err("Point is outside the map".into());
+            }
+            let (zw, zh) = zone_dimensions(&s.zone);
+            (
+                ((x - s.survey_origin.0) * zw as f64).round() as i32,
+                ((y - s.survey_origin.1) * zh as f64).round() as i32,
+            )
+        }
+    };
+    validate_offset(dx, dy, &s.zone)?;
+    Ok((dx, dy))
+}
+
+#[tauri::command]
+fn add_survey(
+    resource: String,
+    placement: Placement,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> Result<RenderPayload, String> {
+    let mut s = state.lock().unwrap();
+    let resource = validate_resource(&resource)?;
+    let (dx, dy) = placement_offset(placement, &s)?;
+    s.checkpoint();
+    s.surveys.push(Survey::new(resource, dx, dy));
+    if s.mode == Mode::Find {
+        s.reroute();
+    }
+    Ok(commit(&app, &s))
+}
+
+/// Change a survey's resource and/or offset. Omitted fields are left as is.
+#[tauri::command]
+fn edit_survey(
+    index: usize,
+    resource: Option<String>,
+    dx: Option<i32>,
+    dy: Option<i32>,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> Result<RenderPayload, String> {
+    let mut s = state.lock().unwrap();
+    let current = s.surveys.get(index).ok_or("No such survey")?;
+    let resource = match resource {
+        Some(r) => validate_resource(&r)?,
+        None => current.resource.clone(),
+    };
+    let dx = dx.unwrap_or(current.dx);
+    let dy = dy.unwrap_or(current.dy);
+    validate_offset(dx, dy, &s.zone)?;
+
+    s.checkpoint();
+    s.surveys[index].edit(resource, dx, dy);
+    if s.mode == Mode::Find {
+        s.reroute();
+    }
+    Ok(commit(&app, &s))
+}
+
+#[tauri::command]
+fn delete_survey(
+    index: usize,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> Result<RenderPayload, String> {
+    let mut s = state.lock().unwrap();
+    if index >= s.surveys.len() {
+        return Err("No such survey".into());
+    }
+    s.checkpoint();
+    s.remove_survey(index);
+    if s.mode == Mode::Find {
+        s.reroute();
+    }
+    emit_completed(&app, s.complete_batch());
+    Ok(commit(&app, &s))
+}
+
+#[derive(Serialize)]
+struct ImportResult {
+    report: ImportReport,
+    payload: RenderPayload,
+}
+
+/// Build a batch from a block of chat text pasted from another machine.
+#[tauri::command]
+fn import_chat_text(text: String, app: AppHandle, state: State<'_, SharedState>) -> ImportResult {
+    let mut s = state.lock().unwrap();
+    s.checkpoint();
+    let report = chat::import_chat_text(&mut s, &text);
+    emit_completed(&app, s.complete_batch());
+    ImportResult {
+        report,
+        payload: commit(&app, &s),
+    }
+}
+
+/// Turn-by-turn directions along the local player's route.
+#[tauri::command]
+fn get_directions(state: State<'_, SharedState>) -> Vec<Leg> {
+    let s = state.lock().unwrap();
+    directions::route_legs(&s)
+}
+
+#[derive(Serialize)]
+struct HotkeyStatus {
+    bindings: Bindings,
+    /// Why some of them couldn't be registered
+    error: Option<String>,
+}
+
+fn hotkey_status(s: &AppState) -> HotkeyStatus {
+    HotkeyStatus {
+        bindings: s.settings.hotkeys.clone(),
+        error: s.hotkey_error.clone(),
+    }
+}
+
+#[tauri::command]
+fn get_hotkeys(state: State<'_, SharedState>) -> HotkeyStatus {
+    let s = state.lock().unwrap();
+    hotkey_status(&s)
+}
+
+/// Bind `action` to `accelerator` (e.g. "Ctrl+Alt+F"), or unbind it with
+/// `None`. Conflicting bindings are rejected and the old ones kept.
+#[tauri::command]
+fn set_hotkey(
+    action: HotkeyAction,
+    accelerator: Option<String>,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> Result<HotkeyStatus, String> {
+    let mut s = state.lock().unwrap();
+    let mut bindings = s.settings.hotkeys.clone();
+    match accelerator.filter(|a| !a.trim().is_empty()) {
+        Some(a) => bindings.insert(action, a.trim().to_string()),
+        None => bindings.remove(&action),
+    };
+    if let Err(e) = hotkeys::register(&app, &bindings) {
+        let _ = hotkeys::register(&app, &s.settings.hotkeys);
+        return Err(e);
+    }
+    s.settings.hotkeys = bindings;
+    s.hotkey_error = None;
+    if let Err(e) = s.save_settings() {
+        eprintln!("Failed to save settings: {e}");
+    }
+    Ok(hotkey_status(&s))
+}
+
+#[tauri::command]
+fn get_hooks(state: State<'_, SharedState>) -> Vec<Hook> {
+    let s = state.lock().unwrap();
+    s.settings.hooks.clone()
+}
+
+/// Replace every hook. Nothing is saved if any of them is unusable.
+#[tauri::command]
+fn set_hooks(hooks: Vec<Hook>, state: State<'_, SharedState>) -> Result<Vec<Hook>, String> {
+    hooks.iter().try_for_each(hooks::validate)?;
+    let mut s = state.lock().unwrap();
+    s.settings.hooks = hooks;
+    s.start_hooks();
+    if let Err(e) = s.save_settings() {
+        eprintln!("Failed to save settings: {e}");
+    }
+    Ok(s.settings.hooks.clone())
+}
+
+/// Run a hook once with a sample event and report how it went.
+#[tauri::command]
+async fn test_hook(mut hook: Hook) -> Result<(), String> {
+    hooks::validate(&hook)?;
+    hook.timeout_ms = hook.timeout_ms.min(hooks::MAX_TIMEOUT_MS);
+    let sample = Fired::new(hook.event, "Serbule", serde_json::json!({ "test": true }));
+    let payload = serde_json::to_vec(&sample).map_err(|e| e.to_string())?;
+    // A hook can take up to its timeout; keep it off the command threads
+    tauri::async_runtime::spawn_blocking(move || hooks::run(&hook, &payload))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Show or hide the always-on-top overlay. Returns whether it is now visible.
+#[tauri::command]
+fn toggle_overlay(app: AppHandle) -> Result<bool, String> {
+    overlay::toggle(&app)
+}
+
+/// Start the live server with `settings`, or `None` if it's disabled.
+/// Viewers' commands are committed like the app's own.
+fn start_server(app: &AppHandle, settings: &ServerSettings) -> Result<Option<LiveServer>, String> {
+    if !settings.enabled {
+        return Ok(None);
+    }
+    let handle = app.clone();
+    let on_change: server::OnChange = Arc::new(move |s: &AppState, batch| {
+        emit_completed(&handle, batch);
+        commit(&handle, s);
+    });
+    let state = app.state::<SharedState>().inner().clone();
+    LiveServer::start(settings, state, on_change).map(Some)
+}
+
+#[derive(Serialize)]
+struct ServerStatus {
+    settings: ServerSettings,
+    /// Where to open the viewer while the server is running
+    url: Option<String>,
+}
+
+#[tauri::command]
+fn get_server_status(
+    state: State<'_, SharedState>,
+    server: State<'_, ServerState>,
+) -> ServerStatus {
+    let settings = state.lock().unwrap().settings.server.clone();
+    ServerStatus {
+        settings,
+        url: server.lock().unwrap().as_ref().map(LiveServer::url),
+    }
+}
+
+/// Apply new server settings, restarting it. If the new settings can't be
+/// used (e.g. the port is taken) the old server is brought back.
+#[tauri::command]
+fn set_server_settings(
+    mut settings: ServerSettings,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+    server: State<'_, ServerState>,
+) -> Result<ServerStatus, String> {
+    // Never hold the server lock while taking the state lock: a viewer's
+    // command publishes to the server with the state locked
+    let old = state.lock().unwrap().settings.server.clone();
+    // The token isn't the viewer's to change
+    settings.token = old.token.clone();
+    let mut running = server.lock().unwrap();
+    // Free the port before binding again
+    *running = None;
+    match start_server(&app, &settings) {
+        Ok(started) => *running = started,
+        Err(e) => {
+            *running = start_server(&app, &old).ok().flatten();
+            return Err(e);
+        }
+    }
+    let url = running.as_ref().map(LiveServer::url);
+    drop(running);
+
+    let mut s = state.lock().unwrap();
+    s.settings.server = settings.clone();
+    if let Err(e) = s.save_settings() {
+        eprintln!("Failed to save settings: {e}");
+    }
+    Ok(ServerStatus { settings, url })
+}
+
+/// Start peer sync with `settings`, or `None` if it's disabled. Peers'
+/// changes are committed like the app's own.
+fn start_sync(app: &AppHandle, settings: &SyncSettings) -> Result<Option<SyncHub>, String> {
+    if !settings.enabled {
+        return Ok(None);
+    }
+    let handle = app.clone();
+    let on_change: server::OnChange = Arc::new(move |s: &AppState, batch| {
+        emit_completed(&handle, batch);
+        commit(&handle, s);
+    });
+    let state = app.state::<SharedState>().inner().clone();
+    SyncHub::start(settings, state, on_change).map(Some)
+}
+
+#[derive(Serialize)]
+struct SyncStatus {
+    settings: SyncSettings,
+    /// Port peers should connect to while sync is on
+    port: Option<u16>,
+    /// Peers currently connected
+    connected: Vec<String>,
+}
+
+#[tauri::command]
+fn get_sync_status(state: State<'_, SharedState>, sync: State<'_, SyncState>) -> SyncStatus {
+    let settings = state.lock().unwrap().settings.sync.clone();
+    let hub = sync.lock().unwrap();
+    SyncStatus {
+        settings,
+        port: hub.as_ref().map(SyncHub::port),
+        connected: hub.as_ref().map(SyncHub::peers).unwrap_or_default(),
+    }
+}
+
+/// Apply new sync settings, restarting it. If the new settings can't be
+/// used (e.g. the port is taken) the old ones are brought back.
+#[tauri::command]
+fn set_sync_settings(
+    settings: SyncSettings,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+    sync: State<'_, SyncState>,
+) -> Result<SyncStatus, String> {
+    let old = state.lock().unwrap().settings.sync.clone();
+    // Stop the old hub outside the lock: its threads may be mid-commit,
+    // which publishes through it
+    let stopped = sync.lock().unwrap().take();
+    drop(stopped);
+    let started = match start_sync(&app, &settings) {
+        Ok(started) => started,
+        Err(e) => {
+            *sync.lock().unwrap() = start_sync(&app, &old).ok().flatten();
+            return Err(e);
+        }
+    };
+    let port = started.as_ref().map(SyncHub::port);
+    *sync.lock().unwrap() = started;
+
+    let mut s = state.lock().unwrap();
+    s.settings.sync = settings.clone();
+    if let Err(e) = s.save_settings() {
+        eprintln!("Failed to save settings: {e}");
+    }
+    Ok(SyncStatus {
+        settings,
+        port,
+        connected: Vec::new(),
+    })
+}
+
+/// Batches completed this session, oldest first.
+#[tauri::command]
+fn get_batch_history(state: State<'_, SharedState>) -> Vec<ArchivedBatch> {
+    let s = state.lock().unwrap();
+    s.archive.clone()
+}
+
+/// Write the current batch and archived history to `path` as "csv",
+/// "json" or "route".
+#[tauri::command]
+fn export_session(
+    path: String,
+    format: String,
+    state: State<'_, SharedState>,
+) -> Result<(), String> {
+    let s = state.lock().unwrap();
+    export::export_session(&s, std::path::Path::new(&path), &format)
+}
+
+/// Load a session written by `export_session` as JSON, replacing the current one.
+#[tauri::command]
+fn import_session(
+    path: String,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> Result<RenderPayload, String> {
+    let mut s = state.lock().unwrap();
+    let before = s.snapshot();
+    export::import_session(&mut s, std::path::Path::new(&path))?;
+    s.history.record(before);
+    Ok(commit(&app, &s))
+}
+
+/// Draw the current batch to `path` as PNG, or SVG for a `.svg` path.
+#[tauri::command]
+async fn render_image(
+    path: String,
+    width: Option<u32>,
+    state: State<'_, SharedState>,
+) -> Result<(), String> {
+    let payload = {
+        let s = state.lock().unwrap();
+        compute_render_payload(&s)
+    };
+    // Rasterizing takes a while; keep it off the command threads
+    tauri::async_runtime::spawn_blocking(move || {
+        render::render_to_file(&payload, std::path::Path::new(&path), width.unwrap_or(1024))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Compact code for the current batch, to paste into chat.
+#[tauri::command]
+fn get_share_code(state: State<'_, SharedState>) -> String {
+    let s = state.lock().unwrap();
+    share::encode_batch(&s)
+}
+
+#[derive(Serialize)]
+struct ShareImportResult {
+    added: usize,
+    payload: RenderPayload,
+}
+
+/// Load a share code, adding to the current batch (`merge`) or replacing it.
+#[tauri::command]
+fn import_share_code(
+    code: String,
+    merge: bool,
+    app: AppHandle,
+    state: State<'_, SharedState>,
+) -> Result<ShareImportResult, String> {
+    let mut s = state.lock().unwrap();
+    let before = s.snapshot();
+    let added = share::import_code(&mut s, &code, merge)?;
+    s.history.record(before);
+    emit_completed(&app, s.complete_batch());
+    Ok(ShareImportResult {
+        added,
+        payload: commit(&app, &s),
+    })
+}
+
+/// Spawn density for the current zone, optionally for one resource.
+/// `cells` is the grid width (default 50).
+#[tauri::command]
+fn get_heatmap(
+    resource: Option<String>,
+    cells: Option<usize>,
+    state: State<'_, SharedState>,
+) -> Heatmap {
+    let s = state.lock().unwrap();
+    heatmap::density_grid(
+        &s.spawn_history,
+        &s.zone,
+        resource.as_deref(),
+        cells.unwrap_or(50),
+    )
+}
+
+/// Readings and collections over the whole history, grouped by `by`
+/// ("resource", "zone" or "day").
+#[tauri::command]
+fn get_history_totals(by: String, state: State<'_, SharedState>) -> Result<Vec<Total>, String> {
+    let s = state.lock().unwrap();
+    let storage = s.storage.as_ref().ok_or("History database unavailable")?;
+    match by.as_str() {
+        "resource" => storage.totals_by_resource(),
+        "zone" => storage.totals_by_zone(),
+        "day" => storage.totals_by_day(),
+        other => return Err(format!("Unknown grouping: {other}")),
+    }
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn undo(app: AppHandle, state: State<'_, SharedState>) -> RenderPayload {
+    let mut s = state.lock().unwrap();
+    s.undo();
+    commit(&app, &s)
+}
+
+#[tauri::command]
+fn redo(app: AppHandle, state: State<'_, SharedState>) -> RenderPayload {
+    let mut s = state.lock().unwrap();
+    s.redo();
+    commit(&app, &s)
 }
 
 #[tauri::command]
@@ -100,7 +835,6 @@ fn set_log_directory(
     {
         let mut s = state.lock().unwrap();
         s.log_directory = Some(path);
-        s.file_position = 0;
     }
 
     let fw = FileWatcher::start(app, state.inner().clone())
@@ -113,6 +847,11 @@ fn set_log_directory(
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
+
     // On Windows, GUI apps have no console — write panics/errors to a log file
     // so crashes are diagnosable.
     let log_path = std::env::current_exe()
@@ -128,20 +867,137 @@ fn main() {
     }
 
     let shared_state: SharedState = Arc::new(Mutex::new(AppState::default()));
+    let saver = SessionSaver::start({
+        let state = shared_state.clone();
+        move || save_session(&state)
+    });
 
     let result = tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
+        .plugin(
+            tauri_plugin_global_shortcut::Builder::new()
+                .with_handler(hotkeys::on_shortcut)
+                .build(),
+        )
         .manage(shared_state)
+        .manage(saver)
         .manage(Mutex::new(None::<FileWatcher>))
+        .manage(ServerState::default())
+        .manage(SyncState::default())
+        .setup(|app| {
+            let settings_path = app.path().app_config_dir()?.join("settings.json");
+            let data_dir = app.path().app_data_dir()?;
+            {
+                let state = app.state::<SharedState>();
+                let mut s = state.lock().unwrap();
+                s.settings = Settings::load(&settings_path);
+                s.settings_path = Some(settings_path);
+                if s.settings.server.token.is_empty() {
+                    s.settings.server.token = server::new_token();
+                    if let Err(e) = s.save_settings() {
+                        eprintln!("Failed to save settings: {e}");
+                    }
+                }
+                s.load_session(data_dir.join("session.json"));
+                s.open_storage(&data_dir.join("history.db"));
+                s.start_hooks();
+            }
+            {
+                let mut s = app.state::<SharedState>().inner().lock().unwrap();
+                if let Err(e) = hotkeys::register(app.handle(), &s.settings.hotkeys) {
+                    eprintln!("Failed to register global shortcuts: {e}");
+                    s.hotkey_error = Some(e);
+                }
+            }
+            overlay::restore(app.handle());
+
+            let server_settings = app
+                .state::<SharedState>()
+                .lock()
+                .unwrap()
+                .settings
+                .server
+                .clone();
+            match start_server(app.handle(), &server_settings) {
+                Ok(started) => *app.state::<ServerState>().lock().unwrap() = started,
+                Err(e) => eprintln!("Failed to start live server: {e}"),
+            }
+            let sync_settings = app
+                .state::<SharedState>()
+                .lock()
+                .unwrap()
+                .settings
+                .sync
+                .clone();
+            match start_sync(app.handle(), &sync_settings) {
+                Ok(started) => *app.state::<SyncState>().lock().unwrap() = started,
+                Err(e) => eprintln!("Failed to start sync: {e}"),
+            }
+            let handle = app.handle().clone();
+            app.listen("state-updated", move |event| {
+                if let Some(server) = handle.state::<ServerState>().lock().unwrap().as_ref() {
+                    server.publish(event.payload());
+                }
+            });
+            Ok(())
+        })
+        .on_window_event(|window, event| match window.label() {
+            overlay::LABEL => overlay::on_window_event(window.app_handle(), event),
+            // The hidden overlay would otherwise keep the app alive
+            "main" if matches!(event, WindowEvent::Destroyed) => {
+                // Don't lose changes the saver hasn't written yet
+                save_session(window.app_handle().state::<SharedState>().inner());
+                overlay::save(window.app_handle());
+                window.app_handle().exit(0)
+            }
+            _ => {}
+        })
         .invoke_handler(tauri::generate_handler![
             get_render_state,
             set_mode,
             set_batch_size,
+            finish_batch,
+            discard_last_reading,
+            extend_batch,
             set_player_pos,
+            set_survey_origin,
+            estimate_survey_origin,
+            accept_position_estimate,
             set_map_size,
             set_zone,
             toggle_found,
+            set_survey_status,
+            toggle_resource,
+            set_reroute_margin,
+            add_group_member,
+            set_group_member_pos,
+            remove_group_member,
             clear_surveys,
+            add_survey,
+            edit_survey,
+            delete_survey,
+            import_chat_text,
+            get_batch_history,
+            get_directions,
+            toggle_overlay,
+            get_hotkeys,
+            set_hotkey,
+            get_hooks,
+            set_hooks,
+            test_hook,
+            get_server_status,
+            set_server_settings,
+            get_sync_status,
+            set_sync_settings,
+            get_history_totals,
+            get_heatmap,
+            export_session,
+            import_session,
+            render_image,
+            get_share_code,
+            import_share_code,
+            undo,
+            redo,
             set_log_directory,
         ])
         .run(tauri::generate_context!());
@@ -0,0 +1,79 @@
+use crate::settings::WindowGeometry;
+use crate::SharedState;
+use tauri::{AppHandle, Manager, PhysicalPosition, PhysicalSize, WindowEvent};
+
+/// Window label of the overlay, as declared in `tauri.conf.json`.
+pub const LABEL: &str = "overlay";
+
+/// Put the overlay back where it was last left, and show it if it was open.
+pub fn restore(app: &AppHandle) {
+    let Some(window) = app.get_webview_window(LABEL) else {
+        return;
+    };
+    let state = app.state::<SharedState>();
+    let s = state.lock().unwrap();
+    if let Some(g) = s.settings.overlay {
+        let _ = window.set_position(PhysicalPosition::new(g.x, g.y));
+        let _ = window.set_size(PhysicalSize::new(g.width, g.height));
+    }
+    if s.settings.overlay_visible {
+        let _ = window.show();
+    }
+}
+
+/// Show or hide the overlay. Returns whether it is now visible.
+pub fn toggle(app: &AppHandle) -> Result<bool, String> {
+    let window = app
+        .get_webview_window(LABEL)
+        .ok_or("Overlay window unavailable")?;
+    let visible = !window.is_visible().unwrap_or(false);
+    let shown = if visible {
+        window.show()
+    } else {
+        window.hide()
+    };
+    shown.map_err(|e| e.to_string())?;
+
+    let state = app.state::<SharedState>();
+    let mut s = state.lock().unwrap();
+    s.settings.overlay_visible = visible;
+    if let Err(e) = s.save_settings() {
+        eprintln!("Failed to save settings: {e}");
+    }
+    Ok(visible)
+}
+
+/// Keep track of the overlay's geometry as it is moved or resized, and
+/// save it once the overlay closes.
+pub fn on_window_event(app: &AppHandle, event: &WindowEvent) {
+    match event {
+        WindowEvent::Moved(_) | WindowEvent::Resized(_) => remember_geometry(app),
+        WindowEvent::CloseRequested { .. } | WindowEvent::Destroyed => save(app),
+        _ => {}
+    }
+}
+
+/// Save the settings, with the overlay where it was last left.
+pub fn save(app: &AppHandle) {
+    let state = app.state::<SharedState>();
+    let s = state.lock().unwrap();
+    if let Err(e) = s.save_settings() {
+        eprintln!("Failed to save settings: {e}");
+    }
+}
+
+fn remember_geometry(app: &AppHandle) {
+    let Some(window) = app.get_webview_window(LABEL) else {
+        return;
+    };
+    let (Ok(pos), Ok(size)) = (window.outer_position(), window.inner_size()) else {
+        return;
+    };
+    let state = app.state::<SharedState>();
+    state.lock().unwrap().settings.overlay = Some(WindowGeometry {
+        x: pos.x,
+        y: pos.y,
+        width: size.width,
+        height: size.height,
+    });
+}
@@ -1,33 +1,203 @@
 use crate::state::Survey;
-use crate::survey::zone_dimensions;
+use crate::survey::{survey_position, zone_dimensions};
+use std::collections::BTreeSet;
+
+/// A survey still to be visited: index into the surveys vec and its position in meters.
+type Node = (usize, f64, f64);
 
 /// Compute an optimized visit order for unvisited surveys.
 /// Returns indices into the surveys vec, ordered by path.
+/// The route starts at `player_pos`; survey offsets are measured from
+/// `survey_origin` (both 0.0–1.0 relative).
+/// Surveys whose resource is in `excluded` are left out of the route.
 /// Uses greedy nearest-neighbor followed by 2-opt improvement.
 pub fn find_path(
     player_pos: (f64, f64),
+    survey_origin: (f64, f64),
     surveys: &[Survey],
     zone: &str,
+    excluded: &BTreeSet<String>,
 ) -> Vec<usize> {
+    let start = to_meters(player_pos, zone);
+    let unvisited = unvisited_nodes(survey_origin, surveys, zone, excluded);
+    order_route(start, &unvisited)
+}
+
+/// Like `find_path`, but the route always visits `first` before anything
+/// else. Used to keep a committed next target in place while re-planning.
+pub fn find_path_after(
+    first: usize,
+    player_pos: (f64, f64),
+    survey_origin: (f64, f64),
+    surveys: &[Survey],
+    zone: &str,
+    excluded: &BTreeSet<String>,
+) -> Vec<usize> {
+    let start = to_meters(player_pos, zone);
+    let unvisited = unvisited_nodes(survey_origin, surveys, zone, excluded);
+    let Some(&(_, fx, fy)) = unvisited.iter().find(|n| n.0 == first) else {
+        return order_route(start, &unvisited);
+    };
+
+    let rest: Vec<Node> = unvisited.into_iter().filter(|n| n.0 != first).collect();
+    let mut route = vec![first];
+    route.extend(order_route((fx, fy), &rest));
+    route
+}
+
+/// Divide unvisited surveys among several players and order each share.
+///
+/// `starts` holds each member's start position (0.0–1.0 relative); survey
+/// offsets are measured from `survey_origin`. Returns one route per member,
+/// chosen to keep the longest individual route as short as possible
+/// (min-max multi-TSP). Starts from a nearest-start assignment, then moves
+/// single surveys off the longest route while that shortens it.
+pub fn split_routes(
+    starts: &[(f64, f64)],
+    survey_origin: (f64, f64),
+    surveys: &[Survey],
+    zone: &str,
+    excluded: &BTreeSet<String>,
+) -> Vec<Vec<usize>> {
+    let k = starts.len();
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let starts: Vec<(f64, f64)> = starts.iter().map(|&p| to_meters(p, zone)).collect();
+    let unvisited = unvisited_nodes(survey_origin, surveys, zone, excluded);
+
+    // Initial assignment: each survey goes to the member starting closest to it
+    let mut shares: Vec<Vec<Node>> = vec![Vec::new(); k];
+    for &node in &unvisited {
+        let nearest = (0..k)
+            .min_by(|&a, &b| {
+                distance(starts[a], (node.1, node.2))
+                    .total_cmp(&distance(starts[b], (node.1, node.2)))
+            })
+            .unwrap_or(0);
+        shares[nearest].push(node);
+    }
+
+    let mut lengths: Vec<f64> = (0..k)
+        .map(|m| share_length(starts[m], &shares[m]))
+        .collect();
+
+    // Improvement: relocate one survey from the longest route per pass.
+    // Bounded so a pathological batch can't stall the UI.
+    for _ in 0..unvisited.len() * k {
+        let longest = argmax(&lengths);
+        let current_max = lengths[longest];
+
+        let mut best: Option<(usize, usize, f64, f64)> = None; // (node pos, target, new longest len, new target len)
+        let mut best_max = current_max;
+        for pos in 0..shares[longest].len() {
+            let mut reduced = shares[longest].clone();
+            let node = reduced.remove(pos);
+            let reduced_len = share_length(starts[longest], &reduced);
+
+            for target in (0..k).filter(|&m| m != longest) {
+                let mut grown = shares[target].clone();
+                grown.push(node);
+                let grown_len = share_length(starts[target], &grown);
+
+                let new_max = (0..k)
+                    .map(|m| match m {
+                        m if m == longest => reduced_len,
+                        m if m == target => grown_len,
+                        m => lengths[m],
+                    })
+                    .fold(0.0, f64::max);
+                if new_max < best_max - 1e-6 {
+                    best_max = new_max;
+                    best = Some((pos, target, reduced_len, grown_len));
+                }
+            }
+        }
+
+        match best {
+            Some((pos, target, reduced_len, grown_len)) => {
+                let node = shares[longest].remove(pos);
+                shares[target].push(node);
+                lengths[longest] = reduced_len;
+                lengths[target] = grown_len;
+            }
+            None => break,
+        }
+    }
+
+    (0..k).map(|m| order_route(starts[m], &shares[m])).collect()
+}
+
+/// Length in meters of walking `order` (survey indices) starting at `start`.
+/// Positions are relative (0.0–1.0); offsets are measured from `survey_origin`.
+pub fn route_length(
+    start: (f64, f64),
+    survey_origin: (f64, f64),
+    order: &[usize],
+    surveys: &[Survey],
+    zone: &str,
+) -> f64 {
+    let mut current = to_meters(start, zone);
+    let mut total = 0.0;
+    for s in order.iter().filter_map(|&i| surveys.get(i)) {
+        let next = survey_position(survey_origin, s, zone);
+        total += distance(current, next);
+        current = next;
+    }
+    total
+}
+
+fn to_meters(pos: (f64, f64), zone: &str) -> (f64, f64) {
     let (zw, zh) = zone_dimensions(zone);
-    let zw = zw as f64;
-    let zh = zh as f64;
+    (pos.0 * zw as f64, pos.1 * zh as f64)
+}
 
-    // Player position in meters
-    let px = player_pos.0 * zw;
-    let py = player_pos.1 * zh;
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+fn argmax(values: &[f64]) -> usize {
+    (0..values.len())
+        .max_by(|&a, &b| values[a].total_cmp(&values[b]))
+        .unwrap_or(0)
+}
 
-    // Collect unvisited survey indices and their meter positions
-    let unvisited: Vec<(usize, f64, f64)> = surveys
+/// Collect pending, non-excluded surveys with their meter positions.
+fn unvisited_nodes(
+    survey_origin: (f64, f64),
+    surveys: &[Survey],
+    zone: &str,
+    excluded: &BTreeSet<String>,
+) -> Vec<Node> {
+    surveys
         .iter()
         .enumerate()
-        .filter(|(_, s)| !s.found)
+        .filter(|(_, s)| s.is_pending() && !excluded.contains(&s.resource))
         .map(|(i, s)| {
-            let sx = (px + s.dx as f64).clamp(0.0, zw);
-            let sy = (py + s.dy as f64).clamp(0.0, zh);
+            let (sx, sy) = survey_position(survey_origin, s, zone);
             (i, sx, sy)
         })
-        .collect();
+        .collect()
+}
+
+/// Length of the optimized route through `share` from `start`.
+fn share_length(start: (f64, f64), share: &[Node]) -> f64 {
+    let order = order_route(start, share);
+    let mut current = start;
+    let mut total = 0.0;
+    for idx in order {
+        if let Some(&(_, x, y)) = share.iter().find(|n| n.0 == idx) {
+            total += distance(current, (x, y));
+            current = (x, y);
+        }
+    }
+    total
+}
+
+/// Order `unvisited` into an open path from `start` (meters).
+fn order_route(start: (f64, f64), unvisited: &[Node]) -> Vec<usize> {
+    let (px, py) = start;
 
     if unvisited.is_empty() {
         return Vec::new();
@@ -118,3 +288,90 @@ fn two_opt_delta(dist: &[Vec<f64>], route: &[usize], i: usize, j: usize) -> f64
     // Old edges: a-b and c-d. New edges: a-c and b-d.
     (dist[a][c] + dist[b][d]) - (dist[a][b] + dist[c][d])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CENTER: (f64, f64) = (0.5, 0.5);
+
+    fn surveys(offsets: &[(i32, i32)]) -> Vec<Survey> {
+        offsets
+            .iter()
+            .map(|&(dx, dy)| Survey::new("Copper Ore".into(), dx, dy))
+            .collect()
+    }
+
+    #[test]
+    fn route_length_walks_each_leg_in_order() {
+        let surveys = surveys(&[(30, 40), (30, 0)]);
+        let length = |order: &[usize]| route_length(CENTER, CENTER, order, &surveys, "Serbule");
+        assert!((length(&[0, 1]) - 90.0).abs() < 1e-9);
+        assert!((length(&[1, 0]) - 70.0).abs() < 1e-9);
+        assert_eq!(length(&[]), 0.0);
+    }
+
+    #[test]
+    fn a_committed_target_comes_first() {
+        let surveys = surveys(&[(10, 0), (20, 0), (-100, 0)]);
+        let none = BTreeSet::new();
+        assert_eq!(
+            find_path(CENTER, CENTER, &surveys, "Serbule", &none),
+            [0, 1, 2]
+        );
+        assert_eq!(
+            find_path_after(2, CENTER, CENTER, &surveys, "Serbule", &none),
+            [2, 0, 1]
+        );
+
+        // A target that isn't on the route plans as usual
+        let excluded = BTreeSet::from(["Copper Ore".to_string()]);
+        assert!(find_path_after(2, CENTER, CENTER, &surveys, "Serbule", &excluded).is_empty());
+        assert_eq!(
+            find_path_after(7, CENTER, CENTER, &surveys, "Serbule", &none),
+            [0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn split_routes_assign_every_survey_once() {
+        let surveys = surveys(&[
+            (-120, 0),
+            (-100, 10),
+            (-90, -20),
+            (100, 0),
+            (110, 30),
+            (5, 5),
+        ]);
+        let starts = [CENTER, (0.4, 0.5), (0.6, 0.5)];
+        let routes = split_routes(&starts, CENTER, &surveys, "Serbule", &BTreeSet::new());
+
+        assert_eq!(routes.len(), 3);
+        let mut assigned: Vec<usize> = routes.concat();
+        assigned.sort();
+        assert_eq!(assigned, [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn split_routes_start_each_member_where_they_stand() {
+        let surveys = surveys(&[(-100, 0), (100, 0)]);
+        let west = (0.5 - 100.0 / zone_dimensions("Serbule").0 as f64, 0.5);
+        let routes = split_routes(
+            &[(1.0 - west.0, 0.5), west],
+            CENTER,
+            &surveys,
+            "Serbule",
+            &BTreeSet::new(),
+        );
+        assert_eq!(routes, [vec![1], vec![0]]);
+    }
+
+    #[test]
+    fn split_routes_leave_spare_members_idle() {
+        let surveys = surveys(&[(10, 0)]);
+        let routes = split_routes(&[CENTER; 3], CENTER, &surveys, "Serbule", &BTreeSet::new());
+        assert_eq!(routes.len(), 3);
+        assert_eq!(routes.concat(), [0]);
+        assert!(split_routes(&[], CENTER, &surveys, "Serbule", &BTreeSet::new()).is_empty());
+    }
+}
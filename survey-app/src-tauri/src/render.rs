@@ -0,0 +1,204 @@
+use crate::state::{RenderPayload, SurveyStatus};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use resvg::{tiny_skia, usvg};
+use std::fmt::Write as _;
+
+const BG: &str = "#1e2326";
+const TEXT: &str = "#d3c6aa";
+const GOLD: &str = "#dbbc7f";
+const ACCENT: &str = "#7fbbb3";
+/// resvg maps bare `sans-serif` to Arial, so name common fonts for each OS
+const FONTS: &str = "Segoe UI, Helvetica, Arial, DejaVu Sans, Noto Sans, sans-serif";
+
+/// Zone map artwork, shared with the frontend.
+pub fn zone_map(zone: &str) -> Option<&'static [u8]> {
+    Some(match zone {
+        "Serbule" => include_bytes!("../../src/assets/serbule_map.webp"),
+        "Serbule Hills" => include_bytes!("../../src/assets/serbule_hills_map.webp"),
+        "Eltibule" => include_bytes!("../../src/assets/eltibule_map.webp"),
+        "Ilmari" => include_bytes!("../../src/assets/ilmari_map.webp"),
+        "Kur Mountains" => include_bytes!("../../src/assets/kur_mountains_map.webp"),
+        _ => return None,
+    })
+}
+
+/// Same palette as the map overlay in the app.
+fn status_color(status: SurveyStatus) -> &'static str {
+    match status {
+        SurveyStatus::Pending => "#e67e80",
+        SurveyStatus::Found => ACCENT,
+        SurveyStatus::Skipped => "#7a8478",
+        SurveyStatus::Unreachable => "#9da86c",
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Draw the zone map with the batch on top as a standalone SVG `width`
+/// pixels wide; the height follows the zone's aspect ratio.
+pub fn render_svg(payload: &RenderPayload, width: u32) -> String {
+    let (zw, zh) = (payload.zone_size.0 as f64, payload.zone_size.1 as f64);
+    let w = width.max(64) as f64;
+    let h = (w * zh / zw).round();
+    // Relative (0.0–1.0) position to image pixels
+    let px = |(x, y): (f64, f64)| (x * w, y * h);
+    // Dots are placed by their offset rather than `x`/`y`, which are in the
+    // frontend's on-screen map pixels
+    let dot_pos: Vec<(f64, f64)> = payload
+        .dots
+        .iter()
+        .map(|d| {
+            (
+                (payload.survey_origin.0 * zw + d.dx as f64) / zw * w,
+                (payload.survey_origin.1 * zh + d.dy as f64) / zh * h,
+            )
+        })
+        .collect();
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="{FONTS}">"#
+    );
+    let _ = writeln!(svg, r#"<rect width="{w}" height="{h}" fill="{BG}"/>"#);
+    if let Some(map) = zone_map(&payload.zone) {
+        let _ = writeln!(
+            svg,
+            r#"<image width="{w}" height="{h}" preserveAspectRatio="none" xlink:href="data:image/webp;base64,{}"/>"#,
+            STANDARD.encode(map)
+        );
+    }
+
+    // Where the player has been this batch
+    if !payload.trail.is_empty() {
+        let points: Vec<String> = payload
+            .trail
+            .iter()
+            .chain(std::iter::once(&payload.player_pos))
+            .map(|&p| {
+                let (x, y) = px(p);
+                format!("{x:.1},{y:.1}")
+            })
+            .collect();
+        let _ = writeln!(
+            svg,
+            r#"<polyline points="{}" fill="none" stroke="{GOLD}" stroke-width="1.5" opacity="0.5"/>"#,
+            points.join(" ")
+        );
+    }
+
+    // Planned route(s) through the pending dots
+    let solo = payload.routes.len() == 1;
+    for route in &payload.routes {
+        let (sx, sy) = px(route.start);
+        let mut points = vec![format!("{sx:.1},{sy:.1}")];
+        for &i in &route.path_indices {
+            if payload
+                .dots
+                .get(i)
+                .is_some_and(|d| d.status == SurveyStatus::Pending)
+            {
+                let (x, y) = dot_pos[i];
+                points.push(format!("{x:.1},{y:.1}"));
+            }
+        }
+        if points.len() < 2 {
+            continue;
+        }
+        let color = if solo { ACCENT } else { route.color.as_str() };
+        let _ = writeln!(
+            svg,
+            r#"<polyline points="{}" fill="none" stroke="{color}" stroke-width="2" stroke-dasharray="6 6" opacity="0.8"/>"#,
+            points.join(" ")
+        );
+    }
+
+    for (dot, &(x, y)) in payload.dots.iter().zip(&dot_pos) {
+        let color = status_color(dot.status);
+        let opacity = if dot.excluded { 0.3 } else { 1.0 };
+        let r = if dot.next { 7.0 } else { 5.0 };
+        let ring = if dot.next {
+            format!(r#" stroke="{GOLD}" stroke-width="2""#)
+        } else {
+            format!(r#" stroke="{BG}" stroke-width="1""#)
+        };
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{x:.1}" cy="{y:.1}" r="{r}" fill="{color}"{ring} opacity="{opacity}"/>"#
+        );
+        if !dot.label.is_empty() {
+            let _ = writeln!(
+                svg,
+                r#"<text x="{:.1}" y="{:.1}" font-size="14" font-weight="700" fill="{color}" stroke="{BG}" stroke-width="3" paint-order="stroke" opacity="{opacity}">{}</text>"#,
+                x + 8.0,
+                y + 5.0,
+                xml_escape(&dot.label)
+            );
+        }
+    }
+
+    let (x, y) = px(payload.player_pos);
+    let _ = writeln!(
+        svg,
+        r#"<circle cx="{x:.1}" cy="{y:.1}" r="8" fill="{GOLD}" stroke="{BG}" stroke-width="2"/>"#
+    );
+
+    // Caption
+    let caption = format!("{} — {}", payload.zone, payload.summary);
+    let _ = writeln!(
+        svg,
+        r#"<rect x="8" y="8" width="{:.0}" height="26" rx="6" fill="{BG}" opacity="0.8"/>"#,
+        caption.chars().count() as f64 * 7.5 + 16.0
+    );
+    let _ = writeln!(
+        svg,
+        r#"<text x="16" y="26" font-size="14" fill="{TEXT}">{}</text>"#,
+        xml_escape(&caption)
+    );
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Rasterize `render_svg` output to PNG bytes.
+pub fn render_png(payload: &RenderPayload, width: u32) -> Result<Vec<u8>, String> {
+    let svg = render_svg(payload, width);
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_str(&svg, &options).map_err(|e| e.to_string())?;
+
+    let size = tree.size().to_int_size();
+    let mut pixmap =
+        tiny_skia::Pixmap::new(size.width(), size.height()).ok_or("Image size out of range")?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
+
+/// Widest image we'll draw; a PNG this size is already ~64 MB in memory.
+pub const MAX_WIDTH: u32 = 4096;
+
+/// Write the rendered batch to `path`, as SVG or PNG by its extension.
+pub fn render_to_file(
+    payload: &RenderPayload,
+    path: &std::path::Path,
+    width: u32,
+) -> Result<(), String> {
+    if !(1..=MAX_WIDTH).contains(&width) {
+        return Err(format!("Width must be 1 to {MAX_WIDTH} pixels"));
+    }
+    let is_svg = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+    let bytes = if is_svg {
+        render_svg(payload, width).into_bytes()
+    } else {
+        render_png(payload, width)?
+    };
+    std::fs::write(path, bytes).map_err(|e| e.to_string())
+}
@@ -0,0 +1,521 @@
+use crate::render::zone_map;
+use crate::state::{random_hex, AppState, ArchivedBatch, SurveyStatus};
+use crate::survey::compute_render_payload;
+use serde::{Deserialize, Serialize};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tungstenite::{Message, WebSocket};
+
+/// Read-only map page served at `/`.
+const VIEWER: &str = include_str!("../../src/viewer.html");
+/// How often idle threads wake to check for shutdown and new payloads.
+const POLL: Duration = Duration::from_millis(50);
+
+/// Server options, persisted in `Settings`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerSettings {
+    pub enabled: bool,
+    pub port: u16,
+    /// Listen on every interface instead of just this machine.
+    pub lan: bool,
+    /// Let connected viewers mark surveys, not just watch.
+    pub allow_commands: bool,
+    /// Secret a viewer needs to send commands; generated once per install
+    /// (see `new_token`) and handed out in the viewer link.
+    pub token: String,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 7878,
+            lan: false,
+            allow_commands: false,
+            token: String::new(),
+        }
+    }
+}
+
+/// A random 128-bit access token for `ServerSettings::token`.
+pub fn new_token() -> String {
+    random_hex(16)
+}
+
+/// Called with the state after a remote viewer or peer changed it, and the
+/// batch that change completed, if any.
+pub type OnChange = Arc<dyn Fn(&AppState, Option<ArchivedBatch>) + Send + Sync>;
+
+/// A command sent by a viewer over the WebSocket.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum ClientCommand {
+    ToggleFound { index: usize },
+    SetSurveyStatus { index: usize, status: SurveyStatus },
+}
+
+/// Messages to viewers, besides `state` (see `state_message`).
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum ServerMessage {
+    Hello { commands: bool },
+    Error { message: String },
+}
+
+struct Shared {
+    state: crate::SharedState,
+    on_change: OnChange,
+    allow_commands: bool,
+    token: String,
+    running: AtomicBool,
+    clients: Mutex<Vec<Sender<String>>>,
+}
+
+/// HTTP + WebSocket server for watching the batch from a browser. Serves
+/// the viewer at `/`, the zone map at `/map`, the payload at `/state`, and
+/// streams every payload over `/ws`. Stops when dropped.
+pub struct LiveServer {
+    shared: Arc<Shared>,
+    addr: SocketAddr,
+    accept: Option<JoinHandle<()>>,
+}
+
+impl LiveServer {
+    pub fn start(
+        settings: &ServerSettings,
+        state: crate::SharedState,
+        on_change: OnChange,
+    ) -> Result<Self, String> {
+        let host = if settings.lan { "0.0.0.0" } else { "127.0.0.1" };
+        let listener = TcpListener::bind((host, settings.port))
+            .map_err(|e| format!("Can't listen on port {}: {e}", settings.port))?;
+        let addr = listener.local_addr().map_err(|e| e.to_string())?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+        let shared = Arc::new(Shared {
+            state,
+            on_change,
+            allow_commands: settings.allow_commands,
+            token: settings.token.clone(),
+            running: AtomicBool::new(true),
+            clients: Mutex::new(Vec::new()),
+        });
+        let accept = {
+            let shared = shared.clone();
+            std::thread::spawn(move || accept_loop(listener, shared))
+        };
+        Ok(Self {
+            shared,
+            addr,
+            accept: Some(accept),
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Address to open the viewer at; on the LAN, this machine's address
+    /// as other devices see it. Carries the access token when viewers may
+    /// send commands.
+    pub fn url(&self) -> String {
+        let ip = if self.addr.ip().is_unspecified() {
+            lan_ip().unwrap_or_else(|| "localhost".into())
+        } else {
+            self.addr.ip().to_string()
+        };
+        match self.shared.allow_commands {
+            true => format!("http://{ip}:{}/?token={}", self.port(), self.shared.token),
+            false => format!("http://{ip}:{}/", self.port()),
+        }
+    }
+
+    /// Send a serialized `RenderPayload` to every connected viewer.
+    pub fn publish(&self, payload: &str) {
+        let message = state_message(payload);
+        self.shared
+            .clients
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(message.clone()).is_ok());
+    }
+}
+
+impl Drop for LiveServer {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Relaxed);
+        // Wait for the listener to close so the port can be reused at once
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+    }
+}
+
+/// The local address used to reach other machines. Connecting a UDP
+/// socket sends nothing; it just picks the outgoing interface.
+fn lan_ip() -> Option<String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:80").ok()?;
+    Some(socket.local_addr().ok()?.ip().to_string())
+}
+
+fn to_json(message: &ServerMessage) -> String {
+    serde_json::to_string(message).unwrap_or_default()
+}
+
+/// `{"event":"state","payload":…}` around an already serialized payload.
+fn state_message(payload: &str) -> String {
+    format!(r#"{{"event":"state","payload":{payload}}}"#)
+}
+
+fn accept_loop(listener: TcpListener, shared: Arc<Shared>) {
+    while shared.running.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let shared = shared.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = handle_connection(stream, &shared) {
+                        eprintln!("Live server: {e}");
+                    }
+                });
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(POLL),
+            Err(e) => {
+                eprintln!("Live server: {e}");
+                std::thread::sleep(POLL);
+            }
+        }
+    }
+}
+
+/// Look at the request line and headers without consuming them, so a
+/// WebSocket upgrade can still be handed to tungstenite whole.
+fn peek_head(stream: &TcpStream) -> std::io::Result<Option<String>> {
+    let mut buf = [0u8; 8192];
+    for _ in 0..40 {
+        let n = stream.peek(&mut buf)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        let head = String::from_utf8_lossy(&buf[..n]);
+        match head.find("\r\n\r\n") {
+            Some(end) => return Ok(Some(head[..end].to_string())),
+            // Headers too long to peek at whole; go with what fits
+            None if n == buf.len() => return Ok(Some(head.into_owned())),
+            None => std::thread::sleep(POLL),
+        }
+    }
+    Ok(None)
+}
+
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.split("\r\n").skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+/// Browsers send the page's origin with a WebSocket upgrade; only the
+/// viewer served from here may connect. Other clients send none.
+fn same_origin(head: &str) -> bool {
+    match (header(head, "Origin"), header(head, "Host")) {
+        (None, _) => true,
+        (Some(origin), Some(host)) => origin.strip_prefix("http://") == Some(host),
+        (Some(_), None) => false,
+    }
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then_some(value)
+    })
+}
+
+fn handle_connection(stream: TcpStream, shared: &Shared) -> Result<(), String> {
+    stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .map_err(|e| e.to_string())?;
+    let head = peek_head(&stream)
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default();
+    let mut request_line = head.split("\r\n").next().unwrap_or_default().split(' ');
+    let path = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some(path)) => path,
+        _ => return respond(stream, "405 Method Not Allowed", "text/plain", b"GET only"),
+    };
+    let (route, query) = path.split_once('?').unwrap_or((path, ""));
+
+    match route {
+        "/ws" if !same_origin(&head) => {
+            respond(stream, "403 Forbidden", "text/plain", b"Foreign origin")
+        }
+        "/ws" => {
+            // Commands need the token from the viewer link
+            let commands =
+                shared.allow_commands && query_param(query, "token") == Some(&shared.token);
+            serve_socket(stream, shared, commands)
+        }
+        "/" => respond(
+            stream,
+            "200 OK",
+            "text/html; charset=utf-8",
+            VIEWER.as_bytes(),
+        ),
+        "/state" => {
+            let payload = compute_render_payload(&shared.state.lock().unwrap());
+            let json = serde_json::to_string(&payload).map_err(|e| e.to_string())?;
+            respond(stream, "200 OK", "application/json", json.as_bytes())
+        }
+        "/map" => {
+            // The viewer asks for the zone it's showing; default to the current one
+            let zone = query_param(query, "zone")
+                .map(|z| z.replace("%20", " ").replace('+', " "))
+                .unwrap_or_else(|| shared.state.lock().unwrap().zone.clone());
+            match zone_map(&zone) {
+                Some(bytes) => respond(stream, "200 OK", "image/webp", bytes),
+                None => respond(stream, "404 Not Found", "text/plain", b"No map for zone"),
+            }
+        }
+        _ => respond(stream, "404 Not Found", "text/plain", b"Not found"),
+    }
+}
+
+fn respond(
+    mut stream: TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> Result<(), String> {
+    // Drain the request; every route is a bodiless GET
+    let mut request = [0u8; 4096];
+    let _ = stream.read(&mut request);
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream
+        .write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(body))
+        .map_err(|e| e.to_string())
+}
+
+/// `commands` is whether this viewer may mark surveys.
+fn serve_socket(stream: TcpStream, shared: &Shared, commands: bool) -> Result<(), String> {
+    let mut ws = tungstenite::accept(stream).map_err(|e| e.to_string())?;
+    ws.get_ref()
+        .set_read_timeout(Some(POLL))
+        .map_err(|e| e.to_string())?;
+
+    // Register before reading the state, so a change made in between is
+    // published to this viewer rather than lost
+    let (tx, rx) = mpsc::channel();
+    tx.send(to_json(&ServerMessage::Hello { commands }))
+        .map_err(|e| e.to_string())?;
+    shared.clients.lock().unwrap().push(tx.clone());
+    let first = {
+        let s = shared.state.lock().unwrap();
+        serde_json::to_string(&compute_render_payload(&s)).map_err(|e| e.to_string())?
+    };
+    tx.send(state_message(&first)).map_err(|e| e.to_string())?;
+
+    let result = socket_loop(&mut ws, &rx, &tx, shared, commands);
+    let _ = ws.close(None);
+    let _ = ws.flush();
+    result
+}
+
+fn socket_loop(
+    ws: &mut WebSocket<TcpStream>,
+    rx: &Receiver<String>,
+    tx: &Sender<String>,
+    shared: &Shared,
+    commands: bool,
+) -> Result<(), String> {
+    while shared.running.load(Ordering::Relaxed) {
+        for message in rx.try_iter() {
+            ws.send(Message::text(message)).map_err(|e| e.to_string())?;
+        }
+        match ws.read() {
+            Ok(Message::Text(text)) => {
+                if let Err(message) = run_command(&text, shared, commands) {
+                    let _ = tx.send(to_json(&ServerMessage::Error { message }));
+                }
+            }
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                return Ok(())
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Apply a viewer's command the same way the matching app command would.
+fn run_command(text: &str, shared: &Shared, commands: bool) -> Result<(), String> {
+    if !shared.allow_commands {
+        return Err("This server is read-only".into());
+    }
+    if !commands {
+        return Err("Open the viewer from the app's link to mark surveys".into());
+    }
+    let command: ClientCommand =
+        serde_json::from_str(text).map_err(|e| format!("Bad command: {e}"))?;
+
+    let mut s = shared.state.lock().unwrap();
+    let index = match command {
+        ClientCommand::ToggleFound { index } | ClientCommand::SetSurveyStatus { index, .. } => {
+            index
+        }
+    };
+    if index >= s.surveys.len() {
+        return Err("No such survey".into());
+    }
+    s.checkpoint();
+    match command {
+        ClientCommand::ToggleFound { index } => s.toggle_found(index),
+        ClientCommand::SetSurveyStatus { index, status } => {
+            s.surveys[index].set_status(status);
+            s.reroute();
+        }
+    }
+    let batch = s.complete_batch();
+    (shared.on_change)(&s, batch);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_batch;
+    use tungstenite::client::IntoClientRequest;
+    use tungstenite::stream::MaybeTlsStream;
+
+    type Client = WebSocket<MaybeTlsStream<TcpStream>>;
+
+    fn start(allow_commands: bool) -> (LiveServer, crate::SharedState) {
+        let state = Arc::new(Mutex::new(test_batch()));
+        let settings = ServerSettings {
+            enabled: true,
+            port: 0,
+            allow_commands,
+            token: "secret".into(),
+            ..Default::default()
+        };
+        let server = LiveServer::start(&settings, state.clone(), Arc::new(|_, _| {})).unwrap();
+        (server, state)
+    }
+
+    fn connect(server: &LiveServer, query: &str) -> Client {
+        let url = format!("ws://127.0.0.1:{}/ws{query}", server.port());
+        tungstenite::connect(url).unwrap().0
+    }
+
+    fn next(client: &mut Client) -> serde_json::Value {
+        match client.read().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    fn send(client: &mut Client, command: serde_json::Value) {
+        client.send(Message::text(command.to_string())).unwrap();
+    }
+
+    #[test]
+    fn a_viewer_gets_hello_then_the_state() {
+        let (server, _state) = start(false);
+        let mut client = connect(&server, "");
+        assert_eq!(
+            next(&mut client),
+            serde_json::json!({ "event": "hello", "commands": false })
+        );
+        let state = next(&mut client);
+        assert_eq!(state["event"], "state");
+        assert_eq!(state["payload"]["dots"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn a_read_only_server_rejects_commands() {
+        let (server, state) = start(false);
+        let mut client = connect(&server, "?token=secret");
+        assert_eq!(next(&mut client)["commands"], false);
+        next(&mut client);
+        send(
+            &mut client,
+            serde_json::json!({ "command": "toggle_found", "index": 0 }),
+        );
+        let reply = next(&mut client);
+        assert_eq!(reply["event"], "error");
+        assert_eq!(reply["message"], "This server is read-only");
+        assert!(state.lock().unwrap().surveys[0].is_pending());
+    }
+
+    #[test]
+    fn commands_need_the_token() {
+        let (server, state) = start(true);
+        assert!(server.url().ends_with("/?token=secret"));
+
+        let mut guest = connect(&server, "?token=wrong");
+        assert_eq!(next(&mut guest)["commands"], false);
+        next(&mut guest);
+        send(
+            &mut guest,
+            serde_json::json!({ "command": "toggle_found", "index": 0 }),
+        );
+        assert_eq!(next(&mut guest)["event"], "error");
+        assert!(state.lock().unwrap().surveys[0].is_pending());
+
+        let mut owner = connect(&server, "?token=secret");
+        assert_eq!(next(&mut owner)["commands"], true);
+        next(&mut owner);
+        send(
+            &mut owner,
+            serde_json::json!({ "command": "toggle_found", "index": 0 }),
+        );
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while state.lock().unwrap().surveys[0].is_pending() {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "command never applied"
+            );
+            std::thread::sleep(POLL);
+        }
+    }
+
+    #[test]
+    fn foreign_origins_are_refused() {
+        let (server, _state) = start(true);
+        let url = format!("ws://127.0.0.1:{}/ws?token=secret", server.port());
+        let mut request = url.clone().into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("Origin", "http://evil.example".parse().unwrap());
+        assert!(tungstenite::connect(request).is_err());
+
+        let mut request = url.into_client_request().unwrap();
+        let origin = format!("http://127.0.0.1:{}", server.port());
+        request
+            .headers_mut()
+            .insert("Origin", origin.parse().unwrap());
+        assert!(tungstenite::connect(request).is_ok());
+    }
+
+    #[test]
+    fn tokens_differ() {
+        let token = new_token();
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, new_token());
+    }
+}
@@ -0,0 +1,76 @@
+use crate::hooks::Hook;
+use crate::hotkeys::Bindings;
+use crate::server::ServerSettings;
+use crate::sync::SyncSettings;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Where the overlay window sits, in physical pixels.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// User preferences persisted across sessions as JSON in the app config dir.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Resources left out of routing and numbering.
+    pub excluded_resources: BTreeSet<String>,
+    /// A re-planned route only replaces the current order if it is shorter
+    /// by more than this fraction (0.1 = 10%).
+    pub reroute_margin: f64,
+    /// Last position and size of the overlay; `None` uses the configured default.
+    pub overlay: Option<WindowGeometry>,
+    pub overlay_visible: bool,
+    /// Global shortcut per action; none until the user binds them
+    pub hotkeys: Bindings,
+    /// Embedded HTTP/WebSocket server for viewing the batch elsewhere
+    pub server: ServerSettings,
+    /// Batch sharing with other instances on the local network
+    pub sync: SyncSettings,
+    /// Commands and URLs told about watcher events
+    pub hooks: Vec<Hook>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            excluded_resources: BTreeSet::new(),
+            reroute_margin: 0.1,
+            overlay: None,
+            overlay_visible: false,
+            hotkeys: Bindings::new(),
+            server: ServerSettings::default(),
+            sync: SyncSettings::default(),
+            hooks: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Load settings from disk, falling back to defaults if the file is
+    /// missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+    }
+
+    pub fn is_excluded(&self, resource: &str) -> bool {
+        self.excluded_resources.contains(resource)
+    }
+}
@@ -0,0 +1,245 @@
+use crate::state::{AppState, Mode, Survey, SurveyStatus};
+use crate::survey::{known_zone_dimensions, validate_offset, validate_resource, zone_dimensions};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Most a code may inflate to; a real batch is a few KB.
+const MAX_DECODED: u64 = 1024 * 1024;
+
+/// Short keys keep the code small enough to paste into chat.
+#[derive(Debug, Serialize, Deserialize)]
+struct SharedSurvey {
+    r: String,
+    x: i32,
+    y: i32,
+    #[serde(default, skip_serializing_if = "is_pending")]
+    s: SurveyStatus,
+}
+
+fn is_pending(status: &SurveyStatus) -> bool {
+    *status == SurveyStatus::Pending
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SharedBatch {
+    z: String,
+    /// Where the surveys were read from (0.0–1.0 relative, 3 decimals)
+    p: (f64, f64),
+    s: Vec<SharedSurvey>,
+}
+
+/// Pack the zone, survey origin and surveys as deflated JSON in unpadded
+/// base64url, the same scheme as the character sheet's share links.
+pub fn encode_batch(state: &AppState) -> String {
+    let round = |v: f64| (v * 1000.0).round() / 1000.0;
+    let batch = SharedBatch {
+        z: state.zone.clone(),
+        p: (round(state.survey_origin.0), round(state.survey_origin.1)),
+        s: state
+            .surveys
+            .iter()
+            .map(|s| SharedSurvey {
+                r: s.resource.clone(),
+                x: s.dx,
+                y: s.dy,
+                s: s.status,
+            })
+            .collect(),
+    };
+    let json = serde_json::to_vec(&batch).expect("batch serializes");
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&json).expect("in-memory write");
+    URL_SAFE_NO_PAD.encode(encoder.finish().expect("in-memory write"))
+}
+
+fn decode_batch(code: &str) -> Result<SharedBatch, String> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(code.trim().trim_end_matches('='))
+        .map_err(|_| "Not a valid share code")?;
+    let mut json = Vec::new();
+    DeflateDecoder::new(&bytes[..])
+        .take(MAX_DECODED + 1)
+        .read_to_end(&mut json)
+        .map_err(|_| "Share code is damaged")?;
+    if json.len() as u64 > MAX_DECODED {
+        return Err("Share code is too large".into());
+    }
+    serde_json::from_slice(&json).map_err(|_| "Share code is damaged".to_string())
+}
+
+/// Load a share code. Replacing starts a new batch at the sharer's position;
+/// merging adds their surveys to the current batch, re-based onto our survey
+/// origin, and skips ones we already have. Returns how many surveys were added.
+pub fn import_code(state: &mut AppState, code: &str, merge: bool) -> Result<usize, String> {
+    let mut batch = decode_batch(code)?;
+    if known_zone_dimensions(&batch.z).is_none() {
+        return Err(format!("Unknown zone: {}", batch.z));
+    }
+    for shared in &mut batch.s {
+        shared.r = validate_resource(&shared.r)?;
+        validate_offset(shared.x, shared.y, &batch.z)?;
+    }
+    if !batch.p.0.is_finite() || !batch.p.1.is_finite() {
+        return Err("Share code is damaged".into());
+    }
+    let origin = (batch.p.0.clamp(0.0, 1.0), batch.p.1.clamp(0.0, 1.0));
+
+    if merge && !state.surveys.is_empty() {
+        if batch.z != state.zone {
+            return Err(format!("Code is for {}, not {}", batch.z, state.zone));
+        }
+        let (zw, zh) = zone_dimensions(&state.zone);
+        let shift_x = ((origin.0 - state.survey_origin.0) * zw as f64).round() as i32;
+        let shift_y = ((origin.1 - state.survey_origin.1) * zh as f64).round() as i32;
+
+        let rebased = batch
+            .s
+            .into_iter()
+            .map(
+                |shared| match (shared.x.checked_add(shift_x), shared.y.checked_add(shift_y)) {
+                    (Some(dx), Some(dy)) => Ok((shared, dx, dy)),
+                    _ => Err("Share code has an offset out of range".to_string()),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut added = 0;
+        for (shared, dx, dy) in rebased {
+            let known = state
+                .surveys
+                .iter()
+                .any(|s| s.resource == shared.r && s.dx == dx && s.dy == dy);
+            if !known {
+                let mut survey = Survey::new(shared.r, dx, dy);
+                if !is_pending(&shared.s) {
+                    survey.set_status(shared.s);
+                }
+                state.surveys.push(survey);
+                added += 1;
+            }
+        }
+        if state.mode == Mode::Find {
+            state.reroute();
+        }
+        return Ok(added);
+    }
+
+    let surveys: Vec<Survey> = batch
+        .s
+        .iter()
+        .map(|s| Survey::new(s.r.clone(), s.x, s.y))
+        .collect();
+    state.zone = batch.z;
+    state.player_pos = origin;
+    state.start_batch(&surveys);
+    // Carry statuses over by matching the (deduplicated) batch back up
+    for shared in &batch.s {
+        if let Some(survey) = state
+            .surveys
+            .iter_mut()
+            .find(|s| s.resource == shared.r && s.dx == shared.x && s.dy == shared.y)
+        {
+            if !is_pending(&shared.s) {
+                survey.set_status(shared.s);
+            }
+        }
+    }
+    state.reroute();
+    Ok(state.surveys.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_batch;
+
+    fn batch() -> AppState {
+        let mut s = test_batch();
+        s.surveys[1].set_status(SurveyStatus::Skipped);
+        s
+    }
+
+    fn summary(state: &AppState) -> Vec<(String, i32, i32, SurveyStatus)> {
+        state
+            .surveys
+            .iter()
+            .map(|s| (s.resource.clone(), s.dx, s.dy, s.status))
+            .collect()
+    }
+
+    #[test]
+    fn a_code_replaces_the_batch() {
+        let theirs = batch();
+        let mut ours = AppState::default();
+        assert_eq!(import_code(&mut ours, &encode_batch(&theirs), false), Ok(2));
+        assert_eq!(ours.zone, theirs.zone);
+        assert_eq!(ours.survey_origin, theirs.survey_origin);
+        assert_eq!(summary(&ours), summary(&theirs));
+        assert_eq!(ours.mode, Mode::Find);
+    }
+
+    #[test]
+    fn merging_rebases_and_skips_known_surveys() {
+        let mut theirs = batch();
+        theirs.survey_origin.0 += 0.05;
+        theirs.surveys[0].dx -= 1;
+        let mut ours = batch();
+        let shift = (0.05 * zone_dimensions(&ours.zone).0 as f64).round() as i32;
+        // Their Copper is 1m further west once shifted onto our origin
+        let copper = 10 - 1 + shift;
+
+        assert_eq!(import_code(&mut ours, &encode_batch(&theirs), true), Ok(2));
+        assert_eq!(ours.surveys.len(), 4);
+        assert_eq!((ours.surveys[2].dx, ours.surveys[2].dy), (copper, 20));
+        // Again, and nothing new comes in
+        assert_eq!(import_code(&mut ours, &encode_batch(&theirs), true), Ok(0));
+    }
+
+    #[test]
+    fn names_the_chat_log_cant_produce_are_refused() {
+        let mut theirs = batch();
+        theirs.surveys[0].resource = "<img src=x onerror=alert(1)>".into();
+        let mut ours = AppState::default();
+        assert!(import_code(&mut ours, &encode_batch(&theirs), false).is_err());
+        theirs.surveys[0].resource = "x".repeat(200);
+        assert!(import_code(&mut ours, &encode_batch(&theirs), false).is_err());
+        assert!(ours.surveys.is_empty());
+    }
+
+    #[test]
+    fn offsets_beyond_the_zone_are_refused() {
+        let mut theirs = batch();
+        theirs.surveys[0].dx = i32::MAX;
+        for merge in [false, true] {
+            let mut ours = batch();
+            let before = summary(&ours);
+            assert!(import_code(&mut ours, &encode_batch(&theirs), merge).is_err());
+            assert_eq!(summary(&ours), before);
+        }
+    }
+
+    #[test]
+    fn bad_codes_are_refused() {
+        let mut ours = AppState::default();
+        assert!(import_code(&mut ours, "not a code!", false).is_err());
+        assert!(import_code(&mut ours, "AAAA", false).is_err());
+        assert!(ours.surveys.is_empty());
+    }
+
+    #[test]
+    fn codes_that_inflate_too_far_are_refused() {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(b"[").unwrap();
+        encoder
+            .write_all(&vec![b' '; MAX_DECODED as usize * 4])
+            .unwrap();
+        let code = URL_SAFE_NO_PAD.encode(encoder.finish().unwrap());
+        assert!(code.len() < 20_000);
+        assert_eq!(decode_batch(&code).unwrap_err(), "Share code is too large");
+    }
+}
@@ -1,5 +1,13 @@
+use crate::directions::Leg;
+use crate::estimator::{self, PositionEstimate, SpawnPoint};
+use crate::history::{self, History, LogPosition, SavedSession, Snapshot};
+use crate::hooks::HookRunner;
+use crate::pathfinder;
+use crate::settings::Settings;
+use crate::storage::{LogLine, Storage};
+use crate::survey::{deduplicate_surveys, survey_position, zone_dimensions};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -8,12 +16,130 @@ pub enum Mode {
     Find,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SurveyStatus {
+    #[default]
+    Pending,
+    Found,
+    /// Deliberately left out of the route without being collected
+    Skipped,
+    /// Couldn't be reached (water, walls, out of bounds)
+    Unreachable,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Survey {
+    /// Random and kept across edits, so peers can tell surveys apart
+    pub id: String,
     pub resource: String,
     pub dx: i32, // meters east(+)/west(-)
     pub dy: i32, // meters south(+)/north(-)
-    pub found: bool,
+    pub status: SurveyStatus,
+    /// Unix time (ms) of the last status change
+    pub status_changed_at: Option<u64>,
+    /// Unix time (ms) the resource or offset was last edited
+    pub edited_at: Option<u64>,
+}
+
+/// A survey deleted from the current batch, remembered so peers drop it too.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Removal {
+    pub id: String,
+    /// Unix time (ms)
+    pub at: u64,
+}
+
+/// A reading taken while finding, held for the next batch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedReading {
+    #[serde(flatten)]
+    pub survey: Survey,
+    /// Where the player stood when it was taken
+    pub pos: (f64, f64),
+}
+
+impl Survey {
+    pub fn new(resource: String, dx: i32, dy: i32) -> Self {
+        Self {
+            id: new_survey_id(),
+            resource,
+            dx,
+            dy,
+            status: SurveyStatus::Pending,
+            status_changed_at: None,
+            edited_at: None,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.status == SurveyStatus::Pending
+    }
+
+    pub fn set_status(&mut self, status: SurveyStatus) {
+        if self.status != status {
+            self.status = status;
+            self.status_changed_at = Some(now_ms());
+        }
+    }
+
+    /// Change what and where the survey is.
+    pub fn edit(&mut self, resource: String, dx: i32, dy: i32) {
+        if (&self.resource, self.dx, self.dy) != (&resource, dx, dy) {
+            self.resource = resource;
+            self.dx = dx;
+            self.dy = dy;
+            self.edited_at = Some(now_ms());
+        }
+    }
+
+    /// Unix time (ms) of the latest status change or edit.
+    pub fn changed_at(&self) -> u64 {
+        self.status_changed_at.max(self.edited_at).unwrap_or(0)
+    }
+}
+
+fn new_survey_id() -> String {
+    random_hex(8)
+}
+
+/// `bytes` bytes from the OS's random source, as hex.
+pub fn random_hex(bytes: usize) -> String {
+    let mut buf = vec![0; bytes];
+    getrandom::fill(&mut buf).expect("OS random source unavailable");
+    buf.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Current Unix time in milliseconds.
+pub fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// A motherlode map distance, measured from where the player stood.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MotherlodeReading {
+    pub pos: (f64, f64), // 0.0–1.0 relative
+    pub distance_m: u32,
+}
+
+/// A finished batch kept in the session history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedBatch {
+    pub zone: String,
+    /// Where the batch was surveyed from (0.0–1.0 relative)
+    pub survey_origin: (f64, f64),
+    pub surveys: Vec<Survey>,
+    /// Found surveys (indices into `surveys`) in the order they were collected
+    pub route: Vec<usize>,
+    /// Unix time (ms)
+    pub started_at: Option<u64>,
+    pub completed_at: u64,
+    pub distance_walked_m: f64,
+    /// Item names from the batch's "collected!" lines
+    pub items_collected: Vec<String>,
 }
 
 pub struct AppState {
@@ -21,14 +147,58 @@ pub struct AppState {
     pub zone: String,
     pub surveys: Vec<Survey>,
     pub player_pos: (f64, f64), // 0.0–1.0 relative
+    /// Where the current batch was surveyed from; survey offsets are relative
+    /// to this, so dots stay put while the player walks the route
+    pub survey_origin: (f64, f64),
+    /// Previous player positions this batch, oldest first
+    pub trail: Vec<(f64, f64)>,
     pub map_width: f64,
     pub map_height: f64,
     pub log_directory: Option<PathBuf>,
+    /// Chat log `file_position` points into
+    pub log_file: Option<PathBuf>,
     pub file_position: u64,
     pub batch_size: usize,
     pub path_order: Vec<usize>,
+    /// Survey the player is currently walking to. Stays fixed across
+    /// re-planning until it is collected or leaves the route.
+    pub next_target: Option<usize>,
     /// Buffer for record mode — accumulates survey lines before batch is finalized
     pub record_buffer: Vec<Survey>,
+    /// Unix time (ms) the current batch was recorded
+    pub batch_started_at: Option<u64>,
+    /// Item names collected this batch
+    pub collected_items: Vec<String>,
+    /// Completed batches this session, oldest first
+    pub archive: Vec<ArchivedBatch>,
+    /// Surveys read during Find mode, held for the next batch
+    pub queued: Vec<QueuedReading>,
+    /// Surveys deleted from the current batch
+    pub removed: Vec<Removal>,
+    pub motherlode_readings: Vec<MotherlodeReading>,
+    /// Start positions (0.0–1.0 relative) of other group members; the local
+    /// player is always member 0 at `player_pos`
+    pub group_starts: Vec<(f64, f64)>,
+    /// Per-member routes when surveying as a group; empty when solo.
+    /// `path_order` mirrors member 0's route.
+    pub member_routes: Vec<Vec<usize>>,
+    /// Where resources have been collected, used to estimate the survey
+    /// origin of later batches; seeded from `storage` on startup
+    pub spawn_history: Vec<SpawnPoint>,
+    /// Proposed survey origin for the current batch, inferred from its offsets
+    pub position_estimate: Option<PositionEstimate>,
+    pub history: History,
+    /// Where the session is saved; `None` until the app has resolved its data dir
+    pub session_path: Option<PathBuf>,
+    pub settings: Settings,
+    /// Where `settings` is persisted; `None` until the app has resolved its config dir
+    pub settings_path: Option<PathBuf>,
+    /// Long-term reading/collection history; `None` if the database couldn't be opened
+    pub storage: Option<Storage>,
+    /// Workers for `settings.hooks`; see `start_hooks`
+    pub hook_runner: HookRunner,
+    /// Why the bound global shortcuts couldn't all be registered
+    pub hotkey_error: Option<String>,
 }
 
 impl Default for AppState {
@@ -38,13 +208,596 @@ impl Default for AppState {
             zone: "Serbule".into(),
             surveys: Vec::new(),
             player_pos: (0.5, 0.5),
+            survey_origin: (0.5, 0.5),
+            trail: Vec::new(),
             map_width: 750.0,
             map_height: 750.0,
             log_directory: None,
+            log_file: None,
             file_position: 0,
             batch_size: 5,
             path_order: Vec::new(),
+            next_target: None,
             record_buffer: Vec::new(),
+            batch_started_at: None,
+            collected_items: Vec::new(),
+            archive: Vec::new(),
+            queued: Vec::new(),
+            removed: Vec::new(),
+            motherlode_readings: Vec::new(),
+            group_starts: Vec::new(),
+            member_routes: Vec::new(),
+            spawn_history: Vec::new(),
+            position_estimate: None,
+            history: History::default(),
+            session_path: None,
+            settings: Settings::default(),
+            settings_path: None,
+            storage: None,
+            hook_runner: HookRunner::default(),
+            hotkey_error: None,
+        }
+    }
+}
+
+impl AppState {
+    /// Recompute `path_order` (and per-member routes when surveying as a
+    /// group) from the current surveys and player position.
+    ///
+    /// The committed `next_target` is always visited first, and the existing
+    /// order is kept unless a fresh plan beats it by `reroute_margin`, so dot
+    /// numbers don't reshuffle mid-walk. In a group each member's route is
+    /// kept or replaced the same way; there the target only stays first
+    /// while the first member's route is kept.
+    pub fn reroute(&mut self) {
+        let routable: Vec<usize> = (0..self.surveys.len())
+            .filter(|&i| self.is_routable(i))
+            .collect();
+        if self.next_target.is_some_and(|i| !routable.contains(&i)) {
+            self.next_target = None;
+        }
+
+        if self.group_starts.is_empty() {
+            self.member_routes.clear();
+            let fresh = match self.next_target {
+                Some(next) => pathfinder::find_path_after(
+                    next,
+                    self.player_pos,
+                    self.survey_origin,
+                    &self.surveys,
+                    &self.zone,
+                    &self.settings.excluded_resources,
+                ),
+                None => pathfinder::find_path(
+                    self.player_pos,
+                    self.survey_origin,
+                    &self.surveys,
+                    &self.zone,
+                    &self.settings.excluded_resources,
+                ),
+            };
+            self.path_order =
+                self.stable_order(self.player_pos, &self.path_order, fresh, self.next_target);
+        } else {
+            let starts: Vec<(f64, f64)> = std::iter::once(self.player_pos)
+                .chain(self.group_starts.iter().copied())
+                .collect();
+            let fresh = pathfinder::split_routes(
+                &starts,
+                self.survey_origin,
+                &self.surveys,
+                &self.zone,
+                &self.settings.excluded_resources,
+            );
+            let routes: Vec<Vec<usize>> = fresh
+                .into_iter()
+                .enumerate()
+                .map(|(m, route)| {
+                    let current = self.member_routes.get(m).map_or(&[][..], Vec::as_slice);
+                    let target = if m == 0 { self.next_target } else { None };
+                    self.stable_order(starts[m], current, route, target)
+                })
+                .collect();
+            self.member_routes = routes;
+            self.path_order = self.member_routes[0].clone();
+        }
+
+        self.next_target = self.path_order.first().copied();
+    }
+
+    /// Replace the current batch with `surveys` (deduplicated) and switch to
+    /// Find mode with a fresh route.
+    pub fn start_batch(&mut self, surveys: &[Survey]) {
+        self.start_batch_from(surveys, self.player_pos);
+    }
+
+    /// Like `start_batch`, for surveys read at `origin` rather than where the
+    /// player stands now.
+    pub fn start_batch_from(&mut self, surveys: &[Survey], origin: (f64, f64)) {
+        self.surveys = deduplicate_surveys(surveys);
+        self.removed.clear();
+        self.record_buffer.clear();
+        self.batch_started_at = Some(now_ms());
+        self.collected_items.clear();
+        self.reset_route();
+        self.survey_origin = origin;
+        self.estimate_position();
+        self.reroute();
+        self.mode = Mode::Find;
+    }
+
+    /// Flip a survey between found and pending, then re-plan the route.
+    pub fn toggle_found(&mut self, index: usize) {
+        let Some(survey) = self.surveys.get_mut(index) else {
+            return;
+        };
+        let status = match survey.status {
+            SurveyStatus::Found => SurveyStatus::Pending,
+            _ => SurveyStatus::Found,
+        };
+        survey.set_status(status);
+        self.reroute();
+    }
+
+    pub fn switch_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        if self.mode == Mode::Find {
+            self.reroute();
+        }
+    }
+
+    /// Drop the batch and everything recorded or queued for the next one.
+    pub fn clear_batch(&mut self) {
+        self.surveys.clear();
+        self.removed.clear();
+        self.record_buffer.clear();
+        self.queued.clear();
+        self.reset_route();
+        self.motherlode_readings.clear();
+        self.position_estimate = None;
+        self.mode = Mode::Record;
+    }
+
+    /// Whether every survey in the batch has been dealt with. Surveys of an
+    /// excluded resource are never routed to, so they don't hold it up.
+    pub fn batch_complete(&self) -> bool {
+        !self.surveys.is_empty()
+            && self
+                .surveys
+                .iter()
+                .all(|s| !s.is_pending() || self.settings.is_excluded(&s.resource))
+    }
+
+    /// If every survey in the batch has been dealt with, archive it and move
+    /// on: a full queue becomes the next batch, a partial one carries on
+    /// recording, and with nothing queued it's back to Record mode. Undoing
+    /// the step that completed it takes it back out of the archive.
+    /// Returns the archived batch.
+    pub fn complete_batch(&mut self) -> Option<ArchivedBatch> {
+        if !self.batch_complete() {
+            return None;
+        }
+
+        let mut route: Vec<usize> = (0..self.surveys.len())
+            .filter(|&i| self.surveys[i].status == SurveyStatus::Found)
+            .collect();
+        route.sort_by_key(|&i| self.surveys[i].status_changed_at);
+        let archived = ArchivedBatch {
+            zone: self.zone.clone(),
+            survey_origin: self.survey_origin,
+            surveys: std::mem::take(&mut self.surveys),
+            route,
+            started_at: self.batch_started_at.take(),
+            completed_at: now_ms(),
+            distance_walked_m: self.distance_walked(),
+            items_collected: std::mem::take(&mut self.collected_items),
+        };
+        self.archive.push(archived.clone());
+        self.reset_route();
+
+        // The queue was read wherever the player stood at the time
+        let queued = std::mem::take(&mut self.queued);
+        let origin = queued.last().map_or(self.player_pos, |q| q.pos);
+        let queued: Vec<Survey> = queued.into_iter().map(|q| q.survey).collect();
+        if !queued.is_empty() && queued.len() >= self.batch_size {
+            self.start_batch_from(&queued, origin);
+        } else {
+            self.record_buffer = queued;
+            self.mode = Mode::Record;
+        }
+        Some(archived)
+    }
+
+    /// Meters walked this batch: along the trail to the current position.
+    pub fn distance_walked(&self) -> f64 {
+        let (zw, zh) = zone_dimensions(&self.zone);
+        let points: Vec<(f64, f64)> = self
+            .trail
+            .iter()
+            .chain(std::iter::once(&self.player_pos))
+            .map(|&(x, y)| (x * zw as f64, y * zh as f64))
+            .collect();
+        points
+            .windows(2)
+            .map(|w| ((w[1].0 - w[0].0).powi(2) + (w[1].1 - w[0].1).powi(2)).sqrt())
+            .sum()
+    }
+
+    /// Drop the planned route but keep where the player has been.
+    pub fn clear_plan(&mut self) {
+        self.path_order.clear();
+        self.member_routes.clear();
+        self.next_target = None;
+    }
+
+    /// Forget the current plan, e.g. when a new batch replaces the surveys.
+    /// The player's current spot becomes the new survey origin.
+    pub fn reset_route(&mut self) {
+        self.clear_plan();
+        self.trail.clear();
+        self.survey_origin = self.player_pos;
+    }
+
+    /// The survey an "`item` collected!" line is for: the first pending one
+    /// of that resource along the route, else any pending one of it (an
+    /// excluded resource is never routed to), else the next target, as an
+    /// item isn't always named after the resource that was surveyed.
+    pub fn survey_for_item(&self, item: &str) -> Option<usize> {
+        let matches = |i: usize| {
+            self.surveys
+                .get(i)
+                .is_some_and(|s| s.is_pending() && s.resource == item)
+        };
+        self.path_order
+            .iter()
+            .copied()
+            .find(|&i| matches(i))
+            .or_else(|| (0..self.surveys.len()).find(|&i| matches(i)))
+            .or(self.next_target)
+    }
+
+    /// Mark a survey collected: remember where it spawned and move the
+    /// player there, keeping where they came from in the trail.
+    pub fn collect(&mut self, index: usize, item: String, line: Option<&LogLine>) {
+        let Some(survey) = self.surveys.get_mut(index) else {
+            return;
+        };
+        survey.set_status(SurveyStatus::Found);
+
+        let (sx, sy) = survey_position(self.survey_origin, &self.surveys[index], &self.zone);
+        let spawn = SpawnPoint {
+            zone: self.zone.clone(),
+            resource: self.surveys[index].resource.clone(),
+            x: sx,
+            y: sy,
+        };
+        self.log_collection(&item, Some(&spawn), line);
+        self.spawn_history.push(spawn);
+        self.collected_items.push(item);
+
+        let (zw, zh) = zone_dimensions(&self.zone);
+        self.trail.push(self.player_pos);
+        self.player_pos = (
+            (sx / zw as f64).clamp(0.0, 1.0),
+            (sy / zh as f64).clamp(0.0, 1.0),
+        );
+    }
+
+    /// Record a survey reading in the long-term history. `line` is where
+    /// in the chat log it was read, if it came from there.
+    pub fn log_reading(&self, survey: &Survey, line: Option<&LogLine>) {
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.record_reading(&self.zone, survey, line) {
+                eprintln!("Failed to record reading: {e}");
+            }
+        }
+    }
+
+    /// Record a "collected!" line in the long-term history.
+    pub fn log_collection(&self, item: &str, spawn: Option<&SpawnPoint>, line: Option<&LogLine>) {
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.record_collection(&self.zone, item, spawn, line) {
+                eprintln!("Failed to record collection: {e}");
+            }
+        }
+    }
+
+    /// Attach the history database and seed the estimator from it.
+    pub fn open_storage(&mut self, path: &Path) {
+        match Storage::open(path).and_then(|storage| Ok((storage.spawn_points()?, storage))) {
+            Ok((spawns, storage)) => {
+                self.spawn_history = spawns;
+                self.storage = Some(storage);
+            }
+            Err(e) => eprintln!("Failed to open history database: {e}"),
+        }
+    }
+
+    /// (Re)start workers for `settings.hooks`, logging failures next to the session.
+    pub fn start_hooks(&mut self) {
+        let log = self
+            .session_path
+            .as_ref()
+            .and_then(|p| p.parent())
+            .map(|dir| dir.join("hooks.log"));
+        self.hook_runner = HookRunner::start(&self.settings.hooks, log);
+    }
+
+    /// Re-estimate the survey origin from the current batch's offsets.
+    pub fn estimate_position(&mut self) {
+        self.position_estimate =
+            estimator::estimate_origin(&self.surveys, &self.zone, &self.spawn_history);
+    }
+
+    /// Remove a survey, shifting route indices above it down by one.
+    pub fn remove_survey(&mut self, index: usize) {
+        self.remove_survey_at(index, now_ms());
+    }
+
+    /// Remove a survey deleted at `at` (Unix ms), e.g. by a peer.
+    pub fn remove_survey_at(&mut self, index: usize, at: u64) {
+        if index >= self.surveys.len() {
+            return;
+        }
+        let survey = self.surveys.remove(index);
+        self.removed.push(Removal { id: survey.id, at });
+
+        let shift = |order: &mut Vec<usize>| {
+            order.retain(|&i| i != index);
+            for i in order.iter_mut().filter(|i| **i > index) {
+                *i -= 1;
+            }
+        };
+        shift(&mut self.path_order);
+        for route in &mut self.member_routes {
+            shift(route);
+        }
+        self.next_target = match self.next_target {
+            Some(i) if i == index => None,
+            Some(i) if i > index => Some(i - 1),
+            other => other,
+        };
+    }
+
+    fn is_routable(&self, index: usize) -> bool {
+        self.surveys
+            .get(index)
+            .is_some_and(|s| s.is_pending() && !self.settings.is_excluded(&s.resource))
+    }
+
+    /// Choose between the `current` order walked from `start` (minus
+    /// anything no longer on the route) and a freshly planned one.
+    fn stable_order(
+        &self,
+        start: (f64, f64),
+        current: &[usize],
+        fresh: Vec<usize>,
+        target: Option<usize>,
+    ) -> Vec<usize> {
+        let current: Vec<usize> = current
+            .iter()
+            .copied()
+            .filter(|i| fresh.contains(i))
+            .collect();
+
+        // The current order is only a candidate if it still covers every
+        // survey on the route and honours the committed target.
+        let covers_all = current.len() == fresh.len();
+        let honours_target = target.is_none() || current.first() == target.as_ref();
+        if current.is_empty() || !covers_all || !honours_target {
+            return fresh;
+        }
+
+        let length = |order: &[usize]| {
+            pathfinder::route_length(start, self.survey_origin, order, &self.surveys, &self.zone)
+        };
+        if length(&fresh) < length(&current) * (1.0 - self.settings.reroute_margin) {
+            fresh
+        } else {
+            current
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            mode: self.mode.clone(),
+            zone: self.zone.clone(),
+            surveys: self.surveys.clone(),
+            player_pos: self.player_pos,
+            survey_origin: self.survey_origin,
+            trail: self.trail.clone(),
+            batch_size: self.batch_size,
+            path_order: self.path_order.clone(),
+            next_target: self.next_target,
+            record_buffer: self.record_buffer.clone(),
+            batch_started_at: self.batch_started_at,
+            collected_items: self.collected_items.clone(),
+            queued: self.queued.clone(),
+            removed: self.removed.clone(),
+            motherlode_readings: self.motherlode_readings.clone(),
+            group_starts: self.group_starts.clone(),
+            member_routes: self.member_routes.clone(),
+            archived: self.archive.len(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.mode = snapshot.mode;
+        self.zone = snapshot.zone;
+        self.surveys = snapshot.surveys;
+        self.player_pos = snapshot.player_pos;
+        self.survey_origin = snapshot.survey_origin;
+        self.trail = snapshot.trail;
+        self.batch_size = snapshot.batch_size;
+        self.path_order = snapshot.path_order;
+        self.next_target = snapshot.next_target;
+        self.record_buffer = snapshot.record_buffer;
+        self.batch_started_at = snapshot.batch_started_at;
+        self.collected_items = snapshot.collected_items;
+        self.queued = snapshot.queued;
+        self.removed = snapshot.removed;
+        self.motherlode_readings = snapshot.motherlode_readings;
+        self.group_starts = snapshot.group_starts;
+        self.member_routes = snapshot.member_routes;
+        self.position_estimate = None;
+    }
+
+    /// Record the current session as an undo step before mutating it.
+    pub fn checkpoint(&mut self) {
+        let before = self.snapshot();
+        self.history.record(before);
+    }
+
+    /// Checkpoint for continuous actions like dragging a marker, where one
+    /// gesture should undo as a single step.
+    pub fn checkpoint_drag(&mut self, action: &'static str) {
+        let before = self.snapshot();
+        self.history.record_coalesced(before, action);
+    }
+
+    /// Restore the previous session state. Returns false if there is none.
+    pub fn undo(&mut self) -> bool {
+        let current = self.snapshot();
+        match self.history.undo(current.clone()) {
+            Some(previous) => {
+                let archived = previous.archived;
+                self.restore(previous);
+                self.restore_archive(archived);
+                self.stamp_restored(&current);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Re-apply an undone step. Returns false if there is none.
+    pub fn redo(&mut self) -> bool {
+        let current = self.snapshot();
+        match self.history.redo(current.clone()) {
+            Some(next) => {
+                let archived = next.archived;
+                self.restore(next);
+                self.restore_archive(archived);
+                self.stamp_restored(&current);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Take batches archived since a step back out of the archive (undo),
+    /// or put them back (redo).
+    fn restore_archive(&mut self, len: usize) {
+        while self.archive.len() > len {
+            let batch = self.archive.pop().expect("archive is longer than len");
+            self.history.reopen(batch);
+        }
+        while self.archive.len() < len {
+            match self.history.rearchive() {
+                Some(batch) => self.archive.push(batch),
+                None => break,
+            }
+        }
+    }
+
+    /// Undo and redo bring back old statuses and offsets with their old
+    /// times; stamp whatever they changed in the batch as happening now,
+    /// so peers take it over what they have.
+    fn stamp_restored(&mut self, replaced: &Snapshot) {
+        if replaced.batch_started_at != self.batch_started_at {
+            return;
+        }
+        let now = now_ms();
+        for survey in &mut self.surveys {
+            match replaced.surveys.iter().find(|s| s.id == survey.id) {
+                Some(old) => {
+                    if old.status != survey.status {
+                        survey.status_changed_at = Some(now);
+                    }
+                    if (&old.resource, old.dx, old.dy) != (&survey.resource, survey.dx, survey.dy) {
+                        survey.edited_at = Some(now);
+                    }
+                }
+                // Brought back from a deletion
+                None => survey.edited_at = Some(now),
+            }
+        }
+        for old in &replaced.surveys {
+            if !self.surveys.iter().any(|s| s.id == old.id) {
+                self.removed.push(Removal {
+                    id: old.id.clone(),
+                    at: now,
+                });
+            }
+        }
+    }
+
+    /// Load the saved session (and its undo history), if there is one.
+    /// A file that can't be read is moved aside to `.bak` rather than
+    /// overwritten by the next save.
+    pub fn load_session(&mut self, path: PathBuf) {
+        if path.exists() {
+            match SavedSession::load(&path) {
+                Ok(saved) => self.apply_session(saved),
+                Err(e) => {
+                    let backup = path.with_extension("json.bak");
+                    eprintln!(
+                        "Failed to load session: {e}; moving it to {}",
+                        backup.display()
+                    );
+                    if let Err(e) = std::fs::rename(&path, &backup) {
+                        eprintln!("Failed to move the session aside: {e}");
+                        return;
+                    }
+                }
+            }
+        }
+        self.session_path = Some(path);
+    }
+
+    /// Like `load_session`, for a file that must be there and readable.
+    pub fn open_session(&mut self, path: PathBuf) -> Result<(), String> {
+        let saved = SavedSession::load(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        self.apply_session(saved);
+        self.session_path = Some(path);
+        Ok(())
+    }
+
+    fn apply_session(&mut self, saved: SavedSession) {
+        self.restore(saved.snapshot);
+        self.archive = saved.archive;
+        self.history = saved.history;
+        if let Some(log) = saved.log_position {
+            self.log_file = Some(log.file);
+            self.file_position = log.position;
+        }
+    }
+
+    /// Write the session to disk. A no-op before the data dir is known.
+    pub fn save_session(&self) -> std::io::Result<()> {
+        match &self.session_path {
+            Some(path) => {
+                let log_position = self.log_file.as_ref().map(|file| LogPosition {
+                    file: file.clone(),
+                    position: self.file_position,
+                });
+                history::save_session(
+                    path,
+                    &self.snapshot(),
+                    &self.archive,
+                    &self.history,
+                    log_position.as_ref(),
+                )
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Write `settings` to disk. A no-op before the config dir is known.
+    pub fn save_settings(&self) -> std::io::Result<()> {
+        match &self.settings_path {
+            Some(path) => self.settings.save(path),
+            None => Ok(()),
         }
     }
 }
@@ -54,23 +807,241 @@ pub struct DotRender {
     pub x: f64,
     pub y: f64,
     pub label: String,
-    pub found: bool,
+    pub status: SurveyStatus,
+    /// Resource is filtered out of the route; drawn dimmed and unnumbered
+    pub excluded: bool,
+    /// Group member whose route visits this dot
+    pub member: Option<usize>,
+    /// Committed next target; the dot the player should be walking to
+    pub next: bool,
+    /// Survey lies outside the zone; `x`/`y` are left unclamped
+    pub out_of_bounds: bool,
     pub resource: String,
+    pub dx: i32,
+    pub dy: i32,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct ResourceCount {
     pub name: String,
     pub count: usize,
+    pub excluded: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemberRoute {
+    pub member: usize,
+    pub color: String,
+    pub start: (f64, f64),
+    pub path_indices: Vec<usize>,
+    pub distance_m: f64,
+}
+
+/// Record-mode progress toward a full batch.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordProgress {
+    pub buffered: Vec<Survey>,
+    pub batch_size: usize,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct RenderPayload {
     pub mode: String,
     pub zone: String,
+    /// Zone (width, height) in meters
+    pub zone_size: (u32, u32),
     pub player_pos: (f64, f64),
+    pub survey_origin: (f64, f64),
+    pub trail: Vec<(f64, f64)>,
+    /// Nearest survey origin that would bring every survey into the zone,
+    /// present only when some survey is out of bounds
+    pub suggested_origin: Option<(f64, f64)>,
+    pub position_estimate: Option<PositionEstimate>,
+    pub motherlode_readings: Vec<MotherlodeReading>,
+    pub can_undo: bool,
+    pub can_redo: bool,
     pub dots: Vec<DotRender>,
     pub path_indices: Vec<usize>,
+    /// One route per group member; a single entry for the local player when solo
+    pub routes: Vec<MemberRoute>,
+    pub record: RecordProgress,
+    /// Readings taken in Find mode, waiting for the current batch to finish
+    pub queued: Vec<QueuedReading>,
+    pub completed_batches: usize,
+    /// First step of the turn-by-turn directions, while finding
+    pub next_leg: Option<Leg>,
     pub summary: String,
     pub resources: Vec<ResourceCount>,
 }
+
+/// Test fixture: a batch of Copper Ore (10, 20) and Tin Ore (-30, 5) read
+/// from the middle of the zone.
+#[cfg(test)]
+pub fn test_batch() -> AppState {
+    let mut s = AppState {
+        batch_size: 2,
+        ..Default::default()
+    };
+    s.start_batch(&[
+        Survey::new("Copper Ore".into(), 10, 20),
+        Survey::new("Tin Ore".into(), -30, 5),
+    ]);
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(state: &mut AppState, resources: &[&str]) {
+        let surveys: Vec<Survey> = resources
+            .iter()
+            .enumerate()
+            .map(|(i, r)| Survey::new(r.to_string(), 10 * i as i32, -20))
+            .collect();
+        state.batch_size = surveys.len();
+        state.start_batch(&surveys);
+    }
+
+    #[test]
+    fn a_batch_is_complete_once_nothing_is_pending() {
+        let mut s = AppState::default();
+        assert!(!s.batch_complete());
+        batch(&mut s, &["Copper Ore", "Tin Ore"]);
+        assert!(!s.batch_complete());
+        s.surveys[0].set_status(SurveyStatus::Found);
+        assert!(!s.batch_complete());
+        s.surveys[1].set_status(SurveyStatus::Unreachable);
+        assert!(s.batch_complete());
+    }
+
+    #[test]
+    fn collections_match_surveys_of_the_same_resource() {
+        let mut s = AppState::default();
+        batch(&mut s, &["Copper Ore", "Tin Ore", "Iron Ore"]);
+        s.settings.excluded_resources.insert("Tin Ore".into());
+        s.reroute();
+        assert!(!s.path_order.contains(&1));
+
+        // The excluded node is the one collected, not the next on the route
+        assert_eq!(s.survey_for_item("Tin Ore"), Some(1));
+        assert_eq!(s.survey_for_item("Iron Ore"), Some(2));
+        assert_eq!(s.survey_for_item("Gold Ore"), s.next_target);
+
+        s.collect(1, "Tin Ore".into(), None);
+        assert_eq!(s.survey_for_item("Tin Ore"), s.next_target);
+        assert!(s.surveys[0].is_pending() && s.surveys[2].is_pending());
+
+        s.collect(0, "Copper Ore".into(), None);
+        s.collect(2, "Iron Ore".into(), None);
+        s.reroute();
+        assert_eq!(s.survey_for_item("Gold Ore"), None);
+    }
+
+    #[test]
+    fn excluded_resources_dont_hold_up_a_batch() {
+        let mut s = AppState::default();
+        batch(&mut s, &["Copper Ore", "Tin Ore"]);
+        s.settings.excluded_resources.insert("Tin Ore".into());
+        assert!(!s.batch_complete());
+        s.surveys[0].set_status(SurveyStatus::Found);
+        assert!(s.batch_complete());
+
+        let archived = s.complete_batch().unwrap();
+        assert_eq!(archived.surveys.len(), 2);
+        assert_eq!(s.archive.len(), 1);
+        assert!(s.surveys.is_empty());
+        assert_eq!(s.mode, Mode::Record);
+    }
+
+    #[test]
+    fn a_full_queue_starts_where_it_was_read() {
+        let mut s = AppState::default();
+        batch(&mut s, &["Copper Ore", "Tin Ore"]);
+        let read_at = (0.2, 0.3);
+        s.queued = ["Iron Ore", "Silver Ore"]
+            .iter()
+            .map(|r| QueuedReading {
+                survey: Survey::new(r.to_string(), 5, 5),
+                pos: read_at,
+            })
+            .collect();
+
+        // Walk off to collect the last node before the batch completes
+        s.player_pos = (0.7, 0.8);
+        s.surveys[0].set_status(SurveyStatus::Found);
+        s.surveys[1].set_status(SurveyStatus::Found);
+        s.complete_batch().unwrap();
+
+        assert_eq!(s.mode, Mode::Find);
+        assert_eq!(s.surveys.len(), 2);
+        assert_eq!(s.survey_origin, read_at);
+        assert_eq!(s.player_pos, (0.7, 0.8));
+        assert!(s.queued.is_empty());
+    }
+
+    #[test]
+    fn a_partial_queue_carries_on_recording() {
+        let mut s = AppState::default();
+        batch(&mut s, &["Copper Ore", "Tin Ore"]);
+        s.queued = vec![QueuedReading {
+            survey: Survey::new("Iron Ore".into(), 5, 5),
+            pos: (0.5, 0.5),
+        }];
+        s.surveys[0].set_status(SurveyStatus::Skipped);
+        s.surveys[1].set_status(SurveyStatus::Found);
+        s.complete_batch().unwrap();
+
+        assert_eq!(s.mode, Mode::Record);
+        assert_eq!(s.record_buffer.len(), 1);
+    }
+
+    #[test]
+    fn a_fresh_route_has_to_beat_the_current_one_by_the_margin() {
+        let mut s = AppState::default();
+        s.start_batch(&[
+            Survey::new("Copper Ore".into(), 10, 0),
+            Survey::new("Tin Ore".into(), 20, 0),
+            Survey::new("Iron Ore".into(), 30, 0),
+        ]);
+        let (start, fresh) = (s.player_pos, vec![0, 1, 2]);
+        // 40m against the fresh plan's 30m
+        let current = [0, 2, 1];
+
+        s.settings.reroute_margin = 0.3;
+        assert_eq!(
+            s.stable_order(start, &current, fresh.clone(), None),
+            current
+        );
+        assert_eq!(
+            s.stable_order(start, &current, fresh.clone(), Some(1)),
+            fresh
+        );
+        s.settings.reroute_margin = 0.1;
+        assert_eq!(s.stable_order(start, &current, fresh.clone(), None), fresh);
+
+        // Only surveys still on the route count
+        s.settings.reroute_margin = 0.3;
+        assert_eq!(s.stable_order(start, &[0, 2, 1], vec![0, 2], None), [0, 2]);
+        assert_eq!(s.stable_order(start, &[0, 2], fresh.clone(), None), fresh);
+    }
+
+    #[test]
+    fn group_members_keep_the_surveys_nearest_them() {
+        let mut s = AppState::default();
+        s.start_batch(&[
+            Survey::new("Copper Ore".into(), -100, 0),
+            Survey::new("Tin Ore".into(), 100, 0),
+        ]);
+        s.next_target = Some(1);
+        s.path_order = vec![1, 0];
+
+        let (zw, zh) = zone_dimensions(&s.zone);
+        let (x, y) = survey_position(s.survey_origin, &s.surveys[1], &s.zone);
+        s.group_starts = vec![(x / zw as f64, y / zh as f64)];
+        s.reroute();
+
+        assert_eq!(s.member_routes, [vec![0], vec![1]]);
+        assert_eq!(s.next_target, Some(0));
+    }
+}
@@ -0,0 +1,283 @@
+use crate::estimator::SpawnPoint;
+use crate::state::{now_ms, Survey};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS readings (
+    id       INTEGER PRIMARY KEY,
+    at       INTEGER NOT NULL,
+    zone     TEXT NOT NULL,
+    resource TEXT NOT NULL,
+    dx       INTEGER NOT NULL,
+    dy       INTEGER NOT NULL,
+    source        TEXT,
+    source_offset INTEGER
+);
+CREATE TABLE IF NOT EXISTS collections (
+    id       INTEGER PRIMARY KEY,
+    at       INTEGER NOT NULL,
+    zone     TEXT NOT NULL,
+    item     TEXT NOT NULL,
+    resource TEXT,
+    x        REAL,
+    y        REAL,
+    source        TEXT,
+    source_offset INTEGER
+);
+CREATE INDEX IF NOT EXISTS readings_at ON readings(at);
+CREATE INDEX IF NOT EXISTS collections_at ON collections(at);
+-- Which chat log line a row came from. Rows from pasted text have no
+-- source and are never deduplicated.
+CREATE UNIQUE INDEX IF NOT EXISTS readings_source ON readings(source, source_offset);
+CREATE UNIQUE INDEX IF NOT EXISTS collections_source ON collections(source, source_offset);
+";
+
+/// A chat log line a reading or collection was read from. Recording the
+/// same line twice (e.g. when the log is read again) keeps the first row.
+#[derive(Debug, Clone, Copy)]
+pub struct LogLine<'a> {
+    /// Log file name
+    pub file: &'a str,
+    /// Byte offset of the line in the file
+    pub offset: u64,
+    /// Unix time (ms) from the line's own timestamp
+    pub at: u64,
+}
+
+fn source<'a>(line: Option<&LogLine<'a>>) -> (u64, Option<&'a str>, Option<i64>) {
+    match line {
+        Some(l) => (l.at, Some(l.file), Some(l.offset as i64)),
+        None => (now_ms(), None, None),
+    }
+}
+
+/// Readings and collections grouped under one key (resource, zone or day).
+#[derive(Debug, Clone, Serialize)]
+pub struct Total {
+    pub key: String,
+    pub readings: u64,
+    pub collected: u64,
+}
+
+/// Long-term history of survey readings and collected resources.
+#[derive(Debug)]
+pub struct Storage {
+    conn: Connection,
+}
+
+impl Storage {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// `line` is where in the chat log the reading was seen, if it was.
+    /// Returns whether it was new.
+    pub fn record_reading(
+        &self,
+        zone: &str,
+        survey: &Survey,
+        line: Option<&LogLine>,
+    ) -> rusqlite::Result<bool> {
+        let (at, file, offset) = source(line);
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO readings (at, zone, resource, dx, dy, source, source_offset)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                at as i64,
+                zone,
+                survey.resource,
+                survey.dx,
+                survey.dy,
+                file,
+                offset
+            ],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// `spawn` is where the collected survey was, when it could be matched to one.
+    /// Returns whether the collection was new.
+    pub fn record_collection(
+        &self,
+        zone: &str,
+        item: &str,
+        spawn: Option<&SpawnPoint>,
+        line: Option<&LogLine>,
+    ) -> rusqlite::Result<bool> {
+        let (at, file, offset) = source(line);
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO collections (at, zone, item, resource, x, y, source, source_offset)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                at as i64,
+                zone,
+                item,
+                spawn.map(|p| &p.resource),
+                spawn.map(|p| p.x),
+                spawn.map(|p| p.y),
+                file,
+                offset,
+            ],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// Every located collection, oldest first, for the origin estimator.
+    pub fn spawn_points(&self) -> rusqlite::Result<Vec<SpawnPoint>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT zone, resource, x, y FROM collections
+             WHERE resource IS NOT NULL AND x IS NOT NULL AND y IS NOT NULL
+             ORDER BY at",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(SpawnPoint {
+                zone: row.get(0)?,
+                resource: row.get(1)?,
+                x: row.get(2)?,
+                y: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Collections count under the surveyed resource, or the item name when
+    /// the pickup couldn't be matched to a survey.
+    pub fn totals_by_resource(&self) -> rusqlite::Result<Vec<Total>> {
+        self.totals("resource", "COALESCE(resource, item)")
+    }
+
+    pub fn totals_by_zone(&self) -> rusqlite::Result<Vec<Total>> {
+        self.totals("zone", "zone")
+    }
+
+    /// Grouped by local calendar day (YYYY-MM-DD).
+    pub fn totals_by_day(&self) -> rusqlite::Result<Vec<Total>> {
+        let day = "date(at / 1000, 'unixepoch', 'localtime')";
+        self.totals(day, day)
+    }
+
+    /// `reading_key` and `collection_key` are trusted SQL expressions, never
+    /// user input.
+    fn totals(&self, reading_key: &str, collection_key: &str) -> rusqlite::Result<Vec<Total>> {
+        let sql = format!(
+            "SELECT key, SUM(readings), SUM(collected) FROM (
+                 SELECT {reading_key} AS key, 1 AS readings, 0 AS collected FROM readings
+                 UNION ALL
+                 SELECT {collection_key} AS key, 0, 1 FROM collections
+             )
+             GROUP BY key
+             ORDER BY key"
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Total {
+                key: row.get(0)?,
+                readings: row.get::<_, i64>(1)? as u64,
+                collected: row.get::<_, i64>(2)? as u64,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory() -> Storage {
+        Storage::open(Path::new(":memory:")).unwrap()
+    }
+
+    fn line(offset: u64) -> LogLine<'static> {
+        LogLine {
+            file: "Chat-25-01-02.log",
+            offset,
+            at: 1_735_800_000_000,
+        }
+    }
+
+    fn spawn(resource: &str) -> SpawnPoint {
+        SpawnPoint {
+            zone: "Serbule".into(),
+            resource: resource.into(),
+            x: 100.0,
+            y: 200.0,
+        }
+    }
+
+    #[test]
+    fn totals_group_readings_and_collections() {
+        let db = memory();
+        let copper = Survey::new("Copper Ore".into(), 10, -20);
+        db.record_reading("Serbule", &copper, None).unwrap();
+        db.record_reading("Eltibule", &copper, None).unwrap();
+        db.record_collection("Serbule", "Copper Ore", Some(&spawn("Copper Ore")), None)
+            .unwrap();
+        // Unmatched pickups count under the item name
+        db.record_collection("Serbule", "Tin Ore", None, None)
+            .unwrap();
+
+        let by_resource = db.totals_by_resource().unwrap();
+        let by_resource: Vec<_> = by_resource
+            .iter()
+            .map(|t| (t.key.as_str(), t.readings, t.collected))
+            .collect();
+        assert_eq!(by_resource, [("Copper Ore", 2, 1), ("Tin Ore", 0, 1)]);
+
+        let by_zone = db.totals_by_zone().unwrap();
+        let by_zone: Vec<_> = by_zone
+            .iter()
+            .map(|t| (t.key.as_str(), t.readings, t.collected))
+            .collect();
+        assert_eq!(by_zone, [("Eltibule", 1, 0), ("Serbule", 1, 2)]);
+
+        assert_eq!(db.spawn_points().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn a_log_line_is_recorded_once() {
+        let db = memory();
+        let survey = Survey::new("Copper Ore".into(), 10, -20);
+        assert!(db
+            .record_reading("Serbule", &survey, Some(&line(0)))
+            .unwrap());
+        assert!(!db
+            .record_reading("Serbule", &survey, Some(&line(0)))
+            .unwrap());
+        assert!(db
+            .record_reading("Serbule", &survey, Some(&line(80)))
+            .unwrap());
+        assert!(db
+            .record_collection("Serbule", "Copper Ore", None, Some(&line(120)))
+            .unwrap());
+        assert!(!db
+            .record_collection("Serbule", "Copper Ore", None, Some(&line(120)))
+            .unwrap());
+        // Pasted text has no source, so repeats are kept
+        db.record_reading("Serbule", &survey, None).unwrap();
+        db.record_reading("Serbule", &survey, None).unwrap();
+
+        let totals = db.totals_by_resource().unwrap();
+        assert_eq!((totals[0].readings, totals[0].collected), (4, 1));
+    }
+
+    #[test]
+    fn rows_use_the_line_timestamp() {
+        let db = memory();
+        let survey = Survey::new("Copper Ore".into(), 10, -20);
+        db.record_reading("Serbule", &survey, Some(&line(0)))
+            .unwrap();
+        let at: i64 = db
+            .conn
+            .query_row("SELECT at FROM readings", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(at, 1_735_800_000_000);
+    }
+}
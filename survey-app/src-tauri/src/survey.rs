@@ -1,16 +1,110 @@
-use crate::state::{AppState, DotRender, RenderPayload, ResourceCount};
+use crate::directions;
+use crate::pathfinder;
+use crate::state::{
+    AppState, DotRender, MemberRoute, Mode, RecordProgress, RenderPayload, ResourceCount, Survey,
+    SurveyStatus,
+};
 use std::collections::HashMap;
 
+/// Route colours per group member; member 0 (the local player) matches the player marker.
+const MEMBER_COLORS: [&str; 6] = [
+    "#dbbc7f", "#7fbbb3", "#a7c080", "#d699b6", "#e69875", "#83c092",
+];
+
+/// Longest resource name the chat log can produce.
+pub const MAX_RESOURCE_LEN: usize = 64;
+/// Besides ASCII letters and digits, what a resource name in the chat log
+/// is made of. Names from share codes, peers or the survey form must match.
+pub const RESOURCE_PUNCTUATION: &str = " '-.,()";
+
+/// Trim a resource name and check it's one the chat log could have produced.
+pub fn validate_resource(resource: &str) -> Result<String, String> {
+    let resource = resource.trim();
+    if resource.is_empty() {
+        return Err("Resource name is required".into());
+    }
+    if resource.len() > MAX_RESOURCE_LEN
+        || !resource
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || RESOURCE_PUNCTUATION.contains(c))
+    {
+        return Err(format!("Not a resource name: {resource:?}"));
+    }
+    Ok(resource.to_string())
+}
+
+/// An offset can't span more than the zone itself.
+pub fn validate_offset(dx: i32, dy: i32, zone: &str) -> Result<(), String> {
+    let (zw, zh) = zone_dimensions(zone);
+    if dx.unsigned_abs() > zw || dy.unsigned_abs() > zh {
+        return Err(format!("Offset is larger than {zone} ({zw}m × {zh}m)"));
+    }
+    Ok(())
+}
+
+/// Returns (width_meters, height_meters) for a zone, or `None` if it isn't
+/// one we have a map for.
+pub fn known_zone_dimensions(zone: &str) -> Option<(u32, u32)> {
+    match zone {
+        "Serbule" => Some((2382, 2488)),
+        "Serbule Hills" => Some((2748, 2668)),
+        "Eltibule" => Some((2684, 2778)),
+        "Ilmari" => Some((2920, 2920)),
+        "Kur Mountains" => Some((3000, 3000)),
+        _ => None,
+    }
+}
+
 /// Returns (width_meters, height_meters) for a zone.
 pub fn zone_dimensions(zone: &str) -> (u32, u32) {
-    match zone {
-        "Serbule" => (2382, 2488),
-        "Serbule Hills" => (2748, 2668),
-        "Eltibule" => (2684, 2778),
-        "Ilmari" => (2920, 2920),
-        "Kur Mountains" => (3000, 3000),
-        _ => (2382, 2488), // default to Serbule
+    known_zone_dimensions(zone).unwrap_or((2382, 2488)) // default to Serbule
+}
+
+/// Position of a survey in meters, given the survey origin (0.0–1.0 relative).
+/// Not clamped: a survey may land outside the zone if the origin is misplaced.
+pub fn survey_position(origin: (f64, f64), survey: &Survey, zone: &str) -> (f64, f64) {
+    let (zw, zh) = zone_dimensions(zone);
+    (
+        origin.0 * zw as f64 + survey.dx as f64,
+        origin.1 * zh as f64 + survey.dy as f64,
+    )
+}
+
+/// Whether a position in meters lies within the zone.
+pub fn in_zone(pos: (f64, f64), zone: &str) -> bool {
+    let (zw, zh) = zone_dimensions(zone);
+    (0.0..=zw as f64).contains(&pos.0) && (0.0..=zh as f64).contains(&pos.1)
+}
+
+/// Suggest the survey origin nearest to `origin` that puts every survey
+/// inside the zone. Returns `None` if all surveys already fit, or if they
+/// span more than the zone and no origin can fit them.
+pub fn suggest_origin(origin: (f64, f64), surveys: &[Survey], zone: &str) -> Option<(f64, f64)> {
+    if surveys
+        .iter()
+        .all(|s| in_zone(survey_position(origin, s, zone), zone))
+    {
+        return None;
     }
+
+    let (zw, zh) = zone_dimensions(zone);
+    let zw = zw as f64;
+    let zh = zh as f64;
+    let min_dx = surveys.iter().map(|s| s.dx).min()? as f64;
+    let max_dx = surveys.iter().map(|s| s.dx).max()? as f64;
+    let min_dy = surveys.iter().map(|s| s.dy).min()? as f64;
+    let max_dy = surveys.iter().map(|s| s.dy).max()? as f64;
+
+    // Feasible origins (meters): every origin + offset must lie in [0, size]
+    let (x_lo, x_hi) = (-min_dx, zw - max_dx);
+    let (y_lo, y_hi) = (-min_dy, zh - max_dy);
+    if x_lo > x_hi || y_lo > y_hi {
+        return None;
+    }
+
+    let ox = (origin.0 * zw).clamp(x_lo, x_hi);
+    let oy = (origin.1 * zh).clamp(y_lo, y_hi);
+    Some((ox / zw, oy / zh))
 }
 
 /// Compute pixel positions for all survey dots given current state.
@@ -19,31 +113,42 @@ pub fn compute_render_payload(state: &AppState) -> RenderPayload {
     let zw = zw as f64;
     let zh = zh as f64;
 
-    // Player position in meters
-    let px = state.player_pos.0 * zw;
-    let py = state.player_pos.1 * zh;
+    let routes = member_routes(state);
+    // survey index -> (member, position in that member's route)
+    let mut route_pos: HashMap<usize, (usize, usize)> = HashMap::new();
+    for route in &routes {
+        for (pos, &idx) in route.path_indices.iter().enumerate() {
+            route_pos.insert(idx, (route.member, pos));
+        }
+    }
 
     let mut dots = Vec::with_capacity(state.surveys.len());
     let mut resource_map: HashMap<String, usize> = HashMap::new();
-    let mut found_count = 0usize;
+    let mut status_counts: HashMap<SurveyStatus, usize> = HashMap::new();
 
     for (i, survey) in state.surveys.iter().enumerate() {
+        let excluded = state.settings.is_excluded(&survey.resource);
+
         // Survey position in meters
-        let sx = (px + survey.dx as f64).clamp(0.0, zw);
-        let sy = (py + survey.dy as f64).clamp(0.0, zh);
+        let (sx, sy) = survey_position(state.survey_origin, survey, &state.zone);
+        let out_of_bounds = !in_zone((sx, sy), &state.zone);
 
         // Convert to pixel coordinates
         let pixel_x = sx / zw * state.map_width;
         let pixel_y = sy / zh * state.map_height;
 
-        let label = if survey.found {
-            "\u{00d7}".to_string() // ×
-        } else {
-            // Find this survey's position in path_order for labeling
-            if let Some(path_pos) = state.path_order.iter().position(|&idx| idx == i) {
-                (path_pos + 1).to_string()
-            } else {
-                (i + 1).to_string()
+        let label = match survey.status {
+            SurveyStatus::Found => "\u{00d7}".to_string(),   // ×
+            SurveyStatus::Skipped => "\u{2013}".to_string(), // –
+            SurveyStatus::Unreachable => "!".to_string(),
+            SurveyStatus::Pending if excluded => String::new(),
+            SurveyStatus::Pending => {
+                // Find this survey's position in its member's route for labeling
+                if let Some(&(_, path_pos)) = route_pos.get(&i) {
+                    (path_pos + 1).to_string()
+                } else {
+                    (i + 1).to_string()
+                }
             }
         };
 
@@ -51,38 +156,103 @@ pub fn compute_render_payload(state: &AppState) -> RenderPayload {
             x: pixel_x,
             y: pixel_y,
             label,
-            found: survey.found,
+            status: survey.status,
+            excluded,
+            member: route_pos.get(&i).map(|&(member, _)| member),
+            next: state.next_target == Some(i),
+            out_of_bounds,
             resource: survey.resource.clone(),
+            dx: survey.dx,
+            dy: survey.dy,
         });
 
         *resource_map.entry(survey.resource.clone()).or_insert(0) += 1;
-        if survey.found {
-            found_count += 1;
-        }
+        *status_counts.entry(survey.status).or_insert(0) += 1;
     }
 
     let total = state.surveys.len();
-    let summary = format!("{}/{} found", found_count, total);
+    let count = |status| status_counts.get(&status).copied().unwrap_or(0);
+    let mut summary = format!("{}/{} found", count(SurveyStatus::Found), total);
+    for (status, name) in [
+        (SurveyStatus::Skipped, "skipped"),
+        (SurveyStatus::Unreachable, "unreachable"),
+    ] {
+        if count(status) > 0 {
+            summary.push_str(&format!(", {} {}", count(status), name));
+        }
+    }
 
     let mut resources: Vec<ResourceCount> = resource_map
         .into_iter()
-        .map(|(name, count)| ResourceCount { name, count })
+        .map(|(name, count)| ResourceCount {
+            excluded: state.settings.is_excluded(&name),
+            name,
+            count,
+        })
         .collect();
     resources.sort_by(|a, b| a.name.cmp(&b.name));
 
     RenderPayload {
         mode: format!("{:?}", state.mode).to_lowercase(),
         zone: state.zone.clone(),
+        zone_size: zone_dimensions(&state.zone),
         player_pos: state.player_pos,
+        survey_origin: state.survey_origin,
+        trail: state.trail.clone(),
+        suggested_origin: suggest_origin(state.survey_origin, &state.surveys, &state.zone),
+        position_estimate: state.position_estimate.clone(),
+        motherlode_readings: state.motherlode_readings.clone(),
+        can_undo: state.history.can_undo(),
+        can_redo: state.history.can_redo(),
         dots,
         path_indices: state.path_order.clone(),
+        routes,
+        record: RecordProgress {
+            buffered: state.record_buffer.clone(),
+            batch_size: state.batch_size,
+        },
+        queued: state.queued.clone(),
+        completed_batches: state.archive.len(),
+        next_leg: match state.mode {
+            Mode::Find => directions::route_legs(state).into_iter().next(),
+            Mode::Record => None,
+        },
         summary,
         resources,
     }
 }
 
+/// Build the per-member routes for the payload. When solo this is just the
+/// local player's `path_order`.
+fn member_routes(state: &AppState) -> Vec<MemberRoute> {
+    let starts = std::iter::once(state.player_pos).chain(state.group_starts.iter().copied());
+    let orders: Vec<&Vec<usize>> = if state.member_routes.is_empty() {
+        vec![&state.path_order]
+    } else {
+        state.member_routes.iter().collect()
+    };
+
+    starts
+        .zip(orders)
+        .enumerate()
+        .map(|(member, (start, order))| MemberRoute {
+            member,
+            color: MEMBER_COLORS[member % MEMBER_COLORS.len()].to_string(),
+            start,
+            path_indices: order.clone(),
+            distance_m: pathfinder::route_length(
+                start,
+                state.survey_origin,
+                order,
+                &state.surveys,
+                &state.zone,
+            ),
+        })
+        .collect()
+}
+
 /// Deduplicate surveys by resource name, keeping the last occurrence of each.
-pub fn deduplicate_surveys(surveys: &[crate::state::Survey]) -> Vec<crate::state::Survey> {
+pub fn deduplicate_surveys(surveys: &[Survey]) -> Vec<Survey> {
     let mut seen: HashMap<String, usize> = HashMap::new();
     let mut result = Vec::new();
 
@@ -100,3 +270,40 @@ pub fn deduplicate_surveys(surveys: &[crate::state::Survey]) -> Vec<crate::state
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_zone_edge_is_inside_the_zone() {
+        let (zw, zh) = zone_dimensions("Serbule");
+        let (zw, zh) = (zw as f64, zh as f64);
+        assert!(in_zone((0.0, 0.0), "Serbule"));
+        assert!(in_zone((zw, zh), "Serbule"));
+        assert!(!in_zone((-0.5, 10.0), "Serbule"));
+        assert!(!in_zone((10.0, zh + 0.5), "Serbule"));
+    }
+
+    #[test]
+    fn suggested_origins_bring_surveys_to_the_edge() {
+        let (zw, _) = zone_dimensions("Serbule");
+        let zw = zw as f64;
+        let inside = [Survey::new("Copper Ore".into(), 10, 20)];
+        assert_eq!(suggest_origin((0.5, 0.5), &inside, "Serbule"), None);
+
+        // 50m past the east edge: the origin moves west just far enough
+        let east = [Survey::new("Tin Ore".into(), (zw / 2.0) as i32 + 50, 0)];
+        let origin = suggest_origin((0.5, 0.5), &east, "Serbule").unwrap();
+        assert_eq!(origin.1, 0.5);
+        let (x, _) = survey_position(origin, &east[0], "Serbule");
+        assert!((x - zw).abs() < 1e-6, "{x}");
+
+        // Wider apart than the zone: nothing fits
+        let apart = [
+            Survey::new("Copper Ore".into(), -(zw as i32), 0),
+            Survey::new("Tin Ore".into(), zw as i32, 0),
+        ];
+        assert_eq!(suggest_origin((0.5, 0.5), &apart, "Serbule"), None);
+    }
+}
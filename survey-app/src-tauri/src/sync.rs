@@ -0,0 +1,842 @@
+use crate::server::OnChange;
+use crate::state::{AppState, ArchivedBatch, Mode, Removal, Survey, SurveyStatus};
+use crate::survey::{known_zone_dimensions, validate_offset, validate_resource, zone_dimensions};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const POLL: Duration = Duration::from_millis(50);
+/// Wait between attempts to reach a configured peer.
+const RETRY: Duration = Duration::from_secs(3);
+/// How long a new connection has to prove it knows the group key.
+const HANDSHAKE: Duration = Duration::from_secs(5);
+/// Longest line a peer may send; a batch is a few kilobytes.
+const MAX_LINE: u64 = 256 * 1024;
+
+/// Peer sync options, persisted in `Settings`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncSettings {
+    pub enabled: bool,
+    /// Port to accept peers on, on every interface
+    pub port: u16,
+    /// Peers to connect to, as "host:port"
+    pub peers: Vec<String>,
+    /// Shared by everyone in the group; connections without it are dropped
+    pub key: String,
+}
+
+impl Default for SyncSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 7879,
+            peers: Vec::new(),
+            key: String::new(),
+        }
+    }
+}
+
+/// A peer's current batch, with offsets relative to its survey origin.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncBatch {
+    pub zone: String,
+    pub survey_origin: (f64, f64),
+    pub surveys: Vec<Survey>,
+    /// Surveys the peer deleted from the batch
+    pub removed: Vec<Removal>,
+}
+
+/// One JSON object per line on the wire. Each side starts with `Hello`;
+/// the side that connected sends it first.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SyncMessage {
+    Hello { key: String },
+    Batch(SyncBatch),
+}
+
+impl SyncBatch {
+    pub fn from_state(state: &AppState) -> Self {
+        Self {
+            zone: state.zone.clone(),
+            survey_origin: state.survey_origin,
+            surveys: state.surveys.clone(),
+            removed: state.removed.clone(),
+        }
+    }
+
+    /// A batch just archived, so peers see how it ended.
+    pub fn from_archived(batch: &ArchivedBatch) -> Self {
+        Self {
+            zone: batch.zone.clone(),
+            survey_origin: batch.survey_origin,
+            surveys: batch.surveys.clone(),
+            removed: Vec::new(),
+        }
+    }
+}
+
+/// Breaks ties between changes made in the same millisecond, so both
+/// peers settle on the same status.
+fn rank(status: SurveyStatus) -> u8 {
+    match status {
+        SurveyStatus::Pending => 0,
+        SurveyStatus::Skipped => 1,
+        SurveyStatus::Unreachable => 2,
+        SurveyStatus::Found => 3,
+    }
+}
+
+/// Fold a peer's batch into ours. With no batch of our own (not even one
+/// we deleted every survey from) we take theirs, unless it's already
+/// finished or we're partway through recording.
+/// Otherwise surveys are matched by id and their offsets re-based onto our
+/// survey origin: for statuses and for edits the most recent change wins,
+/// a deletion wins over changes made before it, and new surveys are added.
+/// Returns whether anything changed.
+pub fn merge_batch(state: &mut AppState, batch: &SyncBatch) -> Result<bool, String> {
+    if known_zone_dimensions(&batch.zone).is_none() {
+        return Err(format!("Unknown zone: {}", batch.zone));
+    }
+    let (ox, oy) = batch.survey_origin;
+    if !(0.0..=1.0).contains(&ox) || !(0.0..=1.0).contains(&oy) {
+        return Err("Peer's survey origin is off the map".into());
+    }
+    for survey in &batch.surveys {
+        if validate_resource(&survey.resource)? != survey.resource {
+            return Err(format!("Not a resource name: {:?}", survey.resource));
+        }
+        validate_offset(survey.dx, survey.dy, &batch.zone)?;
+    }
+    let has_pending = batch.surveys.iter().any(Survey::is_pending);
+
+    if state.surveys.is_empty() && state.removed.is_empty() {
+        if !has_pending || !state.record_buffer.is_empty() {
+            return Ok(false);
+        }
+        state.zone = batch.zone.clone();
+        state.start_batch_from(&batch.surveys, batch.survey_origin);
+        state.removed = batch.removed.clone();
+        return Ok(true);
+    }
+
+    if batch.zone != state.zone {
+        return Err(format!("Peer is in {}, not {}", batch.zone, state.zone));
+    }
+    let (zw, zh) = zone_dimensions(&state.zone);
+    let shift_x = ((batch.survey_origin.0 - state.survey_origin.0) * zw as f64).round() as i32;
+    let shift_y = ((batch.survey_origin.1 - state.survey_origin.1) * zh as f64).round() as i32;
+    let rebased = batch
+        .surveys
+        .iter()
+        .map(|remote| {
+            match (
+                remote.dx.checked_add(shift_x),
+                remote.dy.checked_add(shift_y),
+            ) {
+                (Some(dx), Some(dy)) => Ok((remote, dx, dy)),
+                _ => Err("Peer sent an offset out of range".to_string()),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut changed = false;
+    for removal in &batch.removed {
+        if state.removed.iter().any(|r| r.id == removal.id) {
+            continue;
+        }
+        match state.surveys.iter().position(|s| s.id == removal.id) {
+            // Changed here after they deleted it; ours goes back to them
+            Some(i) if state.surveys[i].changed_at() > removal.at => continue,
+            Some(i) => state.remove_survey_at(i, removal.at),
+            None => state.removed.push(removal.clone()),
+        }
+        changed = true;
+    }
+
+    for (remote, dx, dy) in rebased {
+        match state.surveys.iter_mut().find(|s| s.id == remote.id) {
+            Some(survey) => {
+                let theirs = (remote.status_changed_at.unwrap_or(0), rank(remote.status));
+                let ours = (survey.status_changed_at.unwrap_or(0), rank(survey.status));
+                if theirs > ours {
+                    survey.status = remote.status;
+                    survey.status_changed_at = remote.status_changed_at;
+                    changed = true;
+                }
+                let theirs = (remote.edited_at, &remote.resource, dx, dy);
+                let ours = (survey.edited_at, &survey.resource, survey.dx, survey.dy);
+                if theirs > ours {
+                    survey.resource = remote.resource.clone();
+                    survey.dx = dx;
+                    survey.dy = dy;
+                    survey.edited_at = remote.edited_at;
+                    changed = true;
+                }
+            }
+            None => {
+                if let Some(i) = state.removed.iter().position(|r| r.id == remote.id) {
+                    // Deleted here after their last change
+                    if state.removed[i].at >= remote.changed_at() {
+                        continue;
+                    }
+                    state.removed.remove(i);
+                }
+                state.surveys.push(Survey {
+                    dx,
+                    dy,
+                    ..remote.clone()
+                });
+                changed = true;
+            }
+        }
+    }
+    if changed && state.mode == Mode::Find {
+        state.reroute();
+    }
+    Ok(changed)
+}
+
+struct Link {
+    addr: SocketAddr,
+    tx: Sender<String>,
+}
+
+struct Shared {
+    state: crate::SharedState,
+    on_change: OnChange,
+    key: String,
+    running: AtomicBool,
+    links: Mutex<Vec<Link>>,
+    /// Last batch sent, so moving the player doesn't resend it
+    last_sent: Mutex<Option<SyncBatch>>,
+}
+
+/// Keeps this instance's batch in step with peers on the local network.
+/// Accepts peers on a port and keeps connecting to the configured ones;
+/// once both sides have shown the group key, each sends its batch as a
+/// JSON line on connect and whenever it changes. Stops when dropped.
+pub struct SyncHub {
+    shared: Arc<Shared>,
+    port: u16,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl SyncHub {
+    pub fn start(
+        settings: &SyncSettings,
+        state: crate::SharedState,
+        on_change: OnChange,
+    ) -> Result<Self, String> {
+        if settings.key.trim().is_empty() {
+            return Err("Set a group key to sync".into());
+        }
+        let listener = TcpListener::bind(("0.0.0.0", settings.port))
+            .map_err(|e| format!("Can't listen on port {}: {e}", settings.port))?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+        let shared = Arc::new(Shared {
+            state,
+            on_change,
+            key: settings.key.trim().to_string(),
+            running: AtomicBool::new(true),
+            links: Mutex::new(Vec::new()),
+            last_sent: Mutex::new(None),
+        });
+        let mut threads = Vec::new();
+        {
+            let shared = shared.clone();
+            threads.push(std::thread::spawn(move || accept_loop(listener, shared)));
+        }
+        for peer in &settings.peers {
+            let shared = shared.clone();
+            let peer = peer.trim().to_string();
+            threads.push(std::thread::spawn(move || connect_loop(&peer, shared)));
+        }
+        Ok(Self {
+            shared,
+            port,
+            threads,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Addresses of the peers currently connected.
+    pub fn peers(&self) -> Vec<String> {
+        let links = self.shared.links.lock().unwrap();
+        links.iter().map(|l| l.addr.to_string()).collect()
+    }
+
+    /// Send a batch to every peer if it changed since last time.
+    pub fn publish(&self, batch: SyncBatch) {
+        let mut last = self.shared.last_sent.lock().unwrap();
+        if last.as_ref() == Some(&batch) {
+            return;
+        }
+        let line = encode(&SyncMessage::Batch(batch.clone()));
+        *last = Some(batch);
+        self.shared
+            .links
+            .lock()
+            .unwrap()
+            .retain(|link| link.tx.send(line.clone()).is_ok());
+    }
+}
+
+impl Drop for SyncHub {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn encode(message: &SyncMessage) -> String {
+    let mut line = serde_json::to_string(message).unwrap_or_default();
+    line.push('\n');
+    line
+}
+
+/// Next line from a peer, or `None` once it hangs up. A line over
+/// `MAX_LINE` is an error, so a peer can't make us buffer without end.
+fn read_line(reader: &mut BufReader<TcpStream>) -> Result<Option<String>, String> {
+    let mut line = String::new();
+    let n = reader
+        .by_ref()
+        .take(MAX_LINE)
+        .read_line(&mut line)
+        .map_err(|e| e.to_string())?;
+    if n == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') && n as u64 == MAX_LINE {
+        return Err("message too long".into());
+    }
+    Ok(Some(line))
+}
+
+/// Make sure the other side knows the group key. Whoever connected shows
+/// theirs first, so a stranger connecting to us never learns ours.
+fn handshake(
+    writer: &mut TcpStream,
+    reader: &mut BufReader<TcpStream>,
+    key: &str,
+    outgoing: bool,
+) -> Result<(), String> {
+    let hello = encode(&SyncMessage::Hello {
+        key: key.to_string(),
+    });
+    if outgoing {
+        writer
+            .write_all(hello.as_bytes())
+            .map_err(|e| e.to_string())?;
+    }
+    writer
+        .set_read_timeout(Some(HANDSHAKE))
+        .map_err(|e| e.to_string())?;
+    let line = read_line(reader)?.ok_or("hung up before saying hello")?;
+    match serde_json::from_str::<SyncMessage>(&line) {
+        Ok(SyncMessage::Hello { key: theirs }) if theirs == key => {}
+        _ => return Err("wrong group key".into()),
+    }
+    writer.set_read_timeout(None).map_err(|e| e.to_string())?;
+    if !outgoing {
+        writer
+            .write_all(hello.as_bytes())
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn accept_loop(listener: TcpListener, shared: Arc<Shared>) {
+    while shared.running.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let shared = shared.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = run_link(stream, &shared, false) {
+                        eprintln!("Sync: {e}");
+                    }
+                });
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(POLL),
+            Err(e) => {
+                eprintln!("Sync: {e}");
+                std::thread::sleep(POLL);
+            }
+        }
+    }
+}
+
+/// Stay connected to `peer`, reconnecting whenever the link drops.
+fn connect_loop(peer: &str, shared: Arc<Shared>) {
+    while shared.running.load(Ordering::Relaxed) {
+        let addr = peer.to_socket_addrs().ok().and_then(|mut a| a.next());
+        let stream = addr.and_then(|a| TcpStream::connect_timeout(&a, Duration::from_secs(2)).ok());
+        if let Some(stream) = stream {
+            if let Err(e) = run_link(stream, &shared, true) {
+                eprintln!("Sync with {peer}: {e}");
+            }
+        }
+        // Sleep in short steps so shutdown isn't held up
+        let mut waited = Duration::ZERO;
+        while waited < RETRY && shared.running.load(Ordering::Relaxed) {
+            std::thread::sleep(POLL);
+            waited += POLL;
+        }
+    }
+}
+
+/// Exchange batches over one connection until either side goes away.
+/// Incoming lines are read on a second thread; this one writes.
+/// `outgoing` is whether we connected (rather than accepted).
+fn run_link(mut stream: TcpStream, shared: &Arc<Shared>, outgoing: bool) -> Result<(), String> {
+    let addr = stream.peer_addr().map_err(|e| e.to_string())?;
+    stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+    handshake(&mut stream, &mut reader, &shared.key, outgoing)
+        .map_err(|e| format!("{addr}: {e}"))?;
+
+    let (tx, rx) = mpsc::channel();
+    let current = SyncBatch::from_state(&shared.state.lock().unwrap());
+    let _ = tx.send(encode(&SyncMessage::Batch(current)));
+    shared.links.lock().unwrap().push(Link {
+        addr,
+        tx: tx.clone(),
+    });
+
+    let read_thread = {
+        let shared = shared.clone();
+        std::thread::spawn(move || read_loop(reader, &shared))
+    };
+
+    let mut writer = stream;
+    let result = loop {
+        if !shared.running.load(Ordering::Relaxed) || read_thread.is_finished() {
+            break Ok(());
+        }
+        match rx.recv_timeout(POLL) {
+            Ok(line) => {
+                if let Err(e) = writer.write_all(line.as_bytes()) {
+                    break Err(e.to_string());
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break Ok(()),
+        }
+    };
+
+    // Unblocks the reader
+    let _ = writer.shutdown(Shutdown::Both);
+    let _ = read_thread.join();
+    shared
+        .links
+        .lock()
+        .unwrap()
+        .retain(|link| link.addr != addr);
+    result
+}
+
+fn read_loop(mut reader: BufReader<TcpStream>, shared: &Shared) {
+    loop {
+        let line = match read_line(&mut reader) {
+            Ok(Some(line)) => line,
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("Sync: {e}");
+                return;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<SyncMessage>(&line) {
+            Ok(SyncMessage::Batch(batch)) => apply(shared, &batch),
+            Ok(SyncMessage::Hello { .. }) => {}
+            Err(e) => eprintln!("Sync: bad message: {e}"),
+        }
+    }
+}
+
+fn apply(shared: &Shared, batch: &SyncBatch) {
+    let mut s = shared.state.lock().unwrap();
+    let before = s.snapshot();
+    match merge_batch(&mut s, batch) {
+        Ok(true) => {
+            s.history.record(before);
+            let completed = s.complete_batch();
+            (shared.on_change)(&s, completed);
+        }
+        Ok(false) => {}
+        Err(e) => eprintln!("Sync: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_batch;
+    use std::time::Instant;
+
+    fn survey(resource: &str, dx: i32, dy: i32) -> Survey {
+        Survey::new(resource.into(), dx, dy)
+    }
+
+    fn with_batch(surveys: &[Survey]) -> AppState {
+        let mut s = AppState::default();
+        s.start_batch(surveys);
+        s
+    }
+
+    /// Another player who picked up `state`'s batch.
+    fn peer_of(state: &AppState) -> AppState {
+        let mut peer = AppState::default();
+        assert_eq!(
+            merge_batch(&mut peer, &SyncBatch::from_state(state)),
+            Ok(true)
+        );
+        peer
+    }
+
+    fn set(survey: &mut Survey, status: SurveyStatus, at: u64) {
+        survey.status = status;
+        survey.status_changed_at = Some(at);
+    }
+
+    #[test]
+    fn an_idle_peer_adopts_the_batch() {
+        let mut theirs = with_batch(&[
+            survey("Copper Ore", 10, 20),
+            survey("Tin Ore", -30, 5),
+            survey("Iron Ore", 0, 40),
+        ]);
+        set(&mut theirs.surveys[0], SurveyStatus::Found, 100);
+        theirs.survey_origin = (0.25, 0.75);
+        theirs.remove_survey_at(2, 200);
+
+        // We stay where we are; only the batch is theirs
+        let mut ours = AppState {
+            player_pos: (0.1, 0.2),
+            ..Default::default()
+        };
+        assert_eq!(
+            merge_batch(&mut ours, &SyncBatch::from_state(&theirs)),
+            Ok(true)
+        );
+        assert_eq!(ours.mode, Mode::Find);
+        assert_eq!(ours.player_pos, (0.1, 0.2));
+        assert_eq!(ours.survey_origin, (0.25, 0.75));
+        assert_eq!(ours.surveys, theirs.surveys);
+        assert_eq!(ours.removed, theirs.removed);
+    }
+
+    #[test]
+    fn finished_batches_and_recording_peers_are_left_alone() {
+        let mut finished = with_batch(&[survey("Copper Ore", 10, 20)]);
+        set(&mut finished.surveys[0], SurveyStatus::Found, 100);
+        let mut idle = AppState::default();
+        assert_eq!(
+            merge_batch(&mut idle, &SyncBatch::from_state(&finished)),
+            Ok(false)
+        );
+
+        let pending = with_batch(&[survey("Copper Ore", 10, 20)]);
+        let mut recording = AppState::default();
+        recording.record_buffer.push(survey("Tin Ore", 1, 1));
+        assert_eq!(
+            merge_batch(&mut recording, &SyncBatch::from_state(&pending)),
+            Ok(false)
+        );
+        assert!(recording.surveys.is_empty());
+    }
+
+    #[test]
+    fn other_zones_are_refused() {
+        let mut ours = with_batch(&[survey("Copper Ore", 10, 20)]);
+        let mut theirs = SyncBatch::from_state(&ours);
+        theirs.zone = "Eltibule".into();
+        assert!(merge_batch(&mut ours, &theirs).is_err());
+        theirs.zone = "Nowhere".into();
+        assert!(merge_batch(&mut ours, &theirs).is_err());
+    }
+
+    #[test]
+    fn malformed_batches_change_nothing() {
+        let mut ours = with_batch(&[survey("Copper Ore", 10, 20)]);
+        let before = ours.surveys.clone();
+        let mut theirs = peer_of(&ours);
+        theirs.surveys.push(survey("Tin Ore", -30, 5));
+
+        let mut huge = SyncBatch::from_state(&theirs);
+        huge.surveys[1].dx = i32::MAX;
+        let mut markup = SyncBatch::from_state(&theirs);
+        markup.surveys[1].resource = "<b>Tin</b>".into();
+        let mut off_map = SyncBatch::from_state(&theirs);
+        off_map.survey_origin = (f64::NAN, 0.5);
+        for batch in [huge, markup, off_map] {
+            assert!(merge_batch(&mut ours, &batch).is_err());
+            assert_eq!(ours.surveys, before);
+        }
+    }
+
+    #[test]
+    fn offsets_are_rebased_onto_our_origin() {
+        let mut ours = with_batch(&[survey("Copper Ore", 10, 20)]);
+        let mut theirs = peer_of(&ours);
+        // They moved their origin; their offsets moved the other way
+        theirs.survey_origin = (ours.survey_origin.0 + 0.1, ours.survey_origin.1 - 0.05);
+        let (zw, zh) = zone_dimensions(&ours.zone);
+        let shift_x = (0.1 * zw as f64).round() as i32;
+        let shift_y = (-0.05 * zh as f64).round() as i32;
+        theirs.surveys[0].dx -= shift_x;
+        theirs.surveys[0].dy -= shift_y;
+        theirs.surveys.push(survey("Tin Ore", -30, 5));
+
+        assert_eq!(
+            merge_batch(&mut ours, &SyncBatch::from_state(&theirs)),
+            Ok(true)
+        );
+        assert_eq!(ours.surveys.len(), 2);
+        assert_eq!((ours.surveys[0].dx, ours.surveys[0].dy), (10, 20));
+        assert_eq!(
+            (ours.surveys[1].dx, ours.surveys[1].dy),
+            (-30 + shift_x, 5 + shift_y)
+        );
+    }
+
+    #[test]
+    fn the_latest_status_wins() {
+        let mut ours = with_batch(&[survey("Copper Ore", 10, 20)]);
+        let mut theirs = peer_of(&ours);
+        set(&mut ours.surveys[0], SurveyStatus::Skipped, 200);
+
+        set(&mut theirs.surveys[0], SurveyStatus::Found, 100);
+        assert_eq!(
+            merge_batch(&mut ours, &SyncBatch::from_state(&theirs)),
+            Ok(false)
+        );
+        assert_eq!(ours.surveys[0].status, SurveyStatus::Skipped);
+
+        set(&mut theirs.surveys[0], SurveyStatus::Pending, 300);
+        assert_eq!(
+            merge_batch(&mut ours, &SyncBatch::from_state(&theirs)),
+            Ok(true)
+        );
+        assert_eq!(ours.surveys[0].status, SurveyStatus::Pending);
+        assert_eq!(ours.surveys[0].status_changed_at, Some(300));
+    }
+
+    #[test]
+    fn simultaneous_changes_settle_the_same_way_on_both_sides() {
+        let mut a = with_batch(&[survey("Copper Ore", 10, 20)]);
+        let mut b = peer_of(&a);
+        set(&mut a.surveys[0], SurveyStatus::Found, 500);
+        set(&mut b.surveys[0], SurveyStatus::Skipped, 500);
+
+        let from_a = SyncBatch::from_state(&a);
+        let from_b = SyncBatch::from_state(&b);
+        assert_eq!(merge_batch(&mut a, &from_b), Ok(false));
+        assert_eq!(merge_batch(&mut b, &from_a), Ok(true));
+        assert_eq!(a.surveys[0].status, SurveyStatus::Found);
+        assert_eq!(b.surveys[0].status, SurveyStatus::Found);
+    }
+
+    #[test]
+    fn the_latest_edit_wins() {
+        let mut ours = with_batch(&[survey("Copper Ore", 10, 20)]);
+        let mut theirs = peer_of(&ours);
+        theirs.surveys[0].edit("Tin Ore".into(), 40, 20);
+
+        assert_eq!(
+            merge_batch(&mut ours, &SyncBatch::from_state(&theirs)),
+            Ok(true)
+        );
+        assert_eq!(ours.surveys.len(), 1);
+        assert_eq!(ours.surveys[0].resource, "Tin Ore");
+        assert_eq!(ours.surveys[0].dx, 40);
+        // Nothing newer comes back
+        assert_eq!(
+            merge_batch(&mut theirs, &SyncBatch::from_state(&ours)),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn deletions_win_over_older_changes() {
+        let mut ours = test_batch();
+        set(&mut ours.surveys[0], SurveyStatus::Skipped, 100);
+        let mut theirs = peer_of(&ours);
+        theirs.remove_survey_at(0, 200);
+        theirs.remove_survey_at(0, 200);
+        // Tin was changed here after they deleted it
+        set(&mut ours.surveys[1], SurveyStatus::Found, 300);
+
+        assert_eq!(
+            merge_batch(&mut ours, &SyncBatch::from_state(&theirs)),
+            Ok(true)
+        );
+        assert_eq!(ours.surveys.len(), 1);
+        assert_eq!(ours.surveys[0].resource, "Tin Ore");
+        assert_eq!(ours.removed.len(), 1);
+
+        // Tin goes back to them; Copper stays deleted
+        assert_eq!(
+            merge_batch(&mut theirs, &SyncBatch::from_state(&ours)),
+            Ok(true)
+        );
+        assert_eq!(theirs.surveys, ours.surveys);
+        assert_eq!(theirs.removed, ours.removed);
+    }
+
+    #[test]
+    fn a_deleted_survey_stays_deleted() {
+        let mut ours = test_batch();
+        let theirs = peer_of(&ours);
+        ours.remove_survey(0);
+        // Their stale copy still has it
+        assert_eq!(
+            merge_batch(&mut ours, &SyncBatch::from_state(&theirs)),
+            Ok(false)
+        );
+        assert_eq!(ours.surveys.len(), 1);
+    }
+
+    #[test]
+    fn undo_and_redo_reach_peers() {
+        let mut ours = test_batch();
+        let mut theirs = peer_of(&ours);
+
+        ours.checkpoint();
+        ours.toggle_found(0);
+        merge_batch(&mut theirs, &SyncBatch::from_state(&ours)).unwrap();
+        assert_eq!(theirs.surveys[0].status, SurveyStatus::Found);
+
+        std::thread::sleep(Duration::from_millis(2));
+        ours.undo();
+        assert_eq!(
+            merge_batch(&mut theirs, &SyncBatch::from_state(&ours)),
+            Ok(true)
+        );
+        assert!(theirs.surveys[0].is_pending());
+
+        std::thread::sleep(Duration::from_millis(2));
+        ours.redo();
+        assert_eq!(
+            merge_batch(&mut theirs, &SyncBatch::from_state(&ours)),
+            Ok(true)
+        );
+        assert_eq!(theirs.surveys[0].status, SurveyStatus::Found);
+
+        // Undoing a deletion brings the survey back for them too
+        ours.checkpoint();
+        ours.remove_survey(1);
+        merge_batch(&mut theirs, &SyncBatch::from_state(&ours)).unwrap();
+        assert_eq!(theirs.surveys.len(), 1);
+        std::thread::sleep(Duration::from_millis(2));
+        ours.undo();
+        assert_eq!(
+            merge_batch(&mut theirs, &SyncBatch::from_state(&ours)),
+            Ok(true)
+        );
+        assert_eq!(theirs.surveys.len(), 2);
+    }
+
+    fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out waiting for {what}");
+            std::thread::sleep(POLL);
+        }
+    }
+
+    fn hub(state: AppState, key: &str, peers: Vec<String>) -> (SyncHub, crate::SharedState) {
+        let state = Arc::new(Mutex::new(state));
+        let settings = SyncSettings {
+            enabled: true,
+            port: 0,
+            peers,
+            key: key.into(),
+        };
+        let hub = SyncHub::start(&settings, state.clone(), Arc::new(|_, _| {})).unwrap();
+        (hub, state)
+    }
+
+    #[test]
+    fn hubs_need_a_key() {
+        let settings = SyncSettings {
+            enabled: true,
+            port: 0,
+            ..Default::default()
+        };
+        let state = Arc::new(Mutex::new(AppState::default()));
+        assert!(SyncHub::start(&settings, state, Arc::new(|_, _| {})).is_err());
+    }
+
+    #[test]
+    fn two_hubs_share_a_batch() {
+        let batch = test_batch();
+        let (a, a_state) = hub(batch, "group", Vec::new());
+        let peer = format!("127.0.0.1:{}", a.port());
+        let (b, b_state) = hub(AppState::default(), "group", vec![peer.clone()]);
+
+        wait_for("B to adopt A's batch", || {
+            b_state.lock().unwrap().surveys.len() == 2
+        });
+        assert_eq!(a.peers().len(), 1);
+        assert_eq!(b.peers().join(","), peer);
+
+        {
+            let mut s = b_state.lock().unwrap();
+            s.toggle_found(1);
+            b.publish(SyncBatch::from_state(&s));
+        }
+        wait_for("A to see B's change", || {
+            a_state.lock().unwrap().surveys[1].status == SurveyStatus::Found
+        });
+
+        // A peer with the wrong key gets nothing
+        let (_c, c_state) = hub(AppState::default(), "other", vec![peer]);
+        std::thread::sleep(Duration::from_millis(500));
+        assert!(c_state.lock().unwrap().surveys.is_empty());
+        assert_eq!(a.peers().len(), 1);
+    }
+
+    #[test]
+    fn overlong_lines_drop_the_link() {
+        let batch = with_batch(&[survey("Copper Ore", 10, 20)]);
+        let (a, _state) = hub(batch, "group", Vec::new());
+        let mut stream = TcpStream::connect(("127.0.0.1", a.port())).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        stream
+            .write_all(
+                encode(&SyncMessage::Hello {
+                    key: "group".into(),
+                })
+                .as_bytes(),
+            )
+            .unwrap();
+        let hello = read_line(&mut reader).unwrap().unwrap();
+        assert!(hello.contains(r#""type":"hello""#));
+        let first = read_line(&mut reader).unwrap().unwrap();
+        assert!(first.contains("Copper Ore"));
+        wait_for("A to register the link", || a.peers().len() == 1);
+
+        let flood = vec![b'x'; MAX_LINE as usize + 1024];
+        let _ = stream.write_all(&flood);
+        wait_for("A to drop the link", || a.peers().is_empty());
+    }
+}
@@ -1,26 +1,15 @@
-use crate::pathfinder;
-use crate::state::{AppState, Mode, Survey};
-use crate::survey::{compute_render_payload, deduplicate_surveys};
+use crate::chat::{ChatLine, ChatParser};
+use crate::hooks::{Fired, HookEvent};
+use crate::state::{now_ms, AppState, ArchivedBatch, Mode, MotherlodeReading, QueuedReading};
+use crate::storage::LogLine;
 use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use regex::Regex;
+use serde_json::json;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use tauri::{AppHandle, Emitter};
 
-fn survey_regex() -> Regex {
-    Regex::new(r"\[Status\] The (.+) is (\d+)m (east|west) and (\d+)m (north|south)\.").unwrap()
-}
-
-fn collected_regex() -> Regex {
-    Regex::new(r"\[Status\] (.+?) collected!").unwrap()
-}
-
-fn zone_regex() -> Regex {
-    Regex::new(r"Entering Area: (.+)").unwrap()
-}
-
 pub struct FileWatcher {
     _watcher: RecommendedWatcher,
 }
@@ -54,9 +43,7 @@ impl FileWatcher {
 
         // Spawn a thread to process file events
         thread::spawn(move || {
-            let survey_re = survey_regex();
-            let collected_re = collected_regex();
-            let zone_re = zone_regex();
+            let parser = ChatParser::new();
 
             for event in rx {
                 if !matches!(
@@ -66,13 +53,7 @@ impl FileWatcher {
                     continue;
                 }
 
-                process_new_lines(
-                    &app_clone,
-                    &state_clone,
-                    &survey_re,
-                    &collected_re,
-                    &zone_re,
-                );
+                process_new_lines(&app_clone, &state_clone, &parser, false);
             }
         });
 
@@ -80,11 +61,10 @@ impl FileWatcher {
     }
 }
 
+/// Existing content is caught up on without firing hooks; it happened
+/// before anyone was listening.
 fn initial_read(app: &AppHandle, state: &Arc<Mutex<AppState>>) {
-    let survey_re = survey_regex();
-    let collected_re = collected_regex();
-    let zone_re = zone_regex();
-    process_new_lines(app, state, &survey_re, &collected_re, &zone_re);
+    process_new_lines(app, state, &ChatParser::new(), true);
 }
 
 fn find_latest_log(dir: &std::path::Path) -> Option<std::path::PathBuf> {
@@ -102,17 +82,24 @@ fn find_latest_log(dir: &std::path::Path) -> Option<std::path::PathBuf> {
         .map(|e| e.path())
 }
 
+fn batch_recorded(s: &AppState) -> Fired {
+    Fired::new(
+        HookEvent::BatchRecorded,
+        &s.zone,
+        json!({ "surveys": s.surveys }),
+    )
+}
+
 fn process_new_lines(
     app: &AppHandle,
     state: &Arc<Mutex<AppState>>,
-    survey_re: &Regex,
-    collected_re: &Regex,
-    zone_re: &Regex,
+    parser: &ChatParser,
+    catching_up: bool,
 ) {
-    let (dir, pos) = {
+    let (dir, last_file, last_pos) = {
         let s = state.lock().unwrap();
         match &s.log_directory {
-            Some(d) => (d.clone(), s.file_position),
+            Some(d) => (d.clone(), s.log_file.clone(), s.file_position),
             None => return,
         }
     };
@@ -127,7 +114,13 @@ fn process_new_lines(
         Err(_) => return,
     };
 
+    // A new log, or one that was truncated, is read from the start
     let file_len = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let pos = if last_file.as_ref() == Some(&log_path) && last_pos <= file_len {
+        last_pos
+    } else {
+        0
+    };
     if file_len <= pos {
         return;
     }
@@ -136,106 +129,269 @@ fn process_new_lines(
         return;
     }
 
-    let mut buf = String::new();
-    if file.read_to_string(&mut buf).is_err() {
+    let mut buf = Vec::new();
+    if file.read_to_end(&mut buf).is_err() {
         return;
     }
 
-    let new_pos = file_len;
-    let mut state_changed = false;
-    let mut zone_changed = false;
+    // Leave a half-written last line for the next read
+    let complete = buf.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    if complete == 0 {
+        return;
+    }
+    let new_pos = pos + complete as u64;
+    let file_name = log_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
 
-    {
+    let read = {
         let mut s = state.lock().unwrap();
+        s.log_file = Some(log_path);
         s.file_position = new_pos;
+        let mut read = apply_lines(&mut s, parser, &file_name, pos, &buf[..complete]);
+        if !catching_up {
+            s.hook_runner.fire(std::mem::take(&mut read.fired));
+        }
+        read
+    };
+
+    if read.zone_changed {
+        let _ = app.emit("zone-changed", ());
+    }
+
+    for batch in read.completed {
+        crate::emit_completed(app, Some(batch));
+    }
+
+    if read.state_changed {
+        let s = state.lock().unwrap();
+        crate::commit(app, &s);
+    }
+}
+
+/// What applying a run of log lines did.
+#[derive(Default)]
+struct LinesRead {
+    state_changed: bool,
+    zone_changed: bool,
+    completed: Vec<ArchivedBatch>,
+    fired: Vec<Fired>,
+}
+
+/// Apply complete log lines read from `file_name` at byte `start`.
+fn apply_lines(
+    s: &mut AppState,
+    parser: &ChatParser,
+    file_name: &str,
+    start: u64,
+    lines: &[u8],
+) -> LinesRead {
+    let mut state_changed = false;
+    let mut zone_changed = false;
+    let mut completed = Vec::new();
+    let mut fired = Vec::new();
+    let before = s.snapshot();
+
+    let mut offset = start;
+    for raw in lines.split_inclusive(|&b| b == b'\n') {
+        let line_offset = offset;
+        offset += raw.len() as u64;
+        let line = String::from_utf8_lossy(raw);
+        let line = line.trim_end_matches(['\r', '\n']);
+        let Some(chat_line) = parser.parse(line) else {
+            continue;
+        };
+        let source = LogLine {
+            file: file_name,
+            offset: line_offset,
+            at: parser.timestamp(line).unwrap_or_else(now_ms),
+        };
+        let source = Some(&source);
 
-        for line in buf.lines() {
+        match (chat_line, &s.mode) {
             // Zone detection (always active)
-            if let Some(caps) = zone_re.captures(line) {
-                let new_zone = caps[1].trim().to_string();
-                if new_zone != s.zone {
-                    s.zone = new_zone;
-                    zone_changed = true;
-                    state_changed = true;
-                }
+            (ChatLine::Zone(new_zone), _) if new_zone != s.zone => {
+                fired.push(Fired::new(
+                    HookEvent::ZoneChanged,
+                    &new_zone,
+                    json!({ "previous": s.zone }),
+                ));
+                s.zone = new_zone;
+                zone_changed = true;
+                state_changed = true;
             }
+            // Motherlode distances are measured from where the player stands
+            (ChatLine::Motherlode(distance_m), _) => {
+                let pos = s.player_pos;
+                s.motherlode_readings
+                    .push(MotherlodeReading { pos, distance_m });
+                state_changed = true;
+            }
+            (ChatLine::Survey(survey), Mode::Record) => {
+                s.log_reading(&survey, source);
+                s.record_buffer.push(survey);
+
+                // Last-N semantics: keep only last batch_size entries
+                let batch = s.batch_size;
+                if s.record_buffer.len() > batch {
+                    let excess = s.record_buffer.len() - batch;
+                    s.record_buffer.drain(..excess);
+                }
 
-            match s.mode {
-                Mode::Record => {
-                    if let Some(caps) = survey_re.captures(line) {
-                        let resource = caps[1].to_string();
-                        let mut dx: i32 = caps[2].parse().unwrap_or(0);
-                        if &caps[3] == "west" {
-                            dx = -dx;
-                        }
-                        let mut dy: i32 = caps[4].parse().unwrap_or(0);
-                        if &caps[5] == "north" {
-                            dy = -dy;
-                        }
-
-                        s.record_buffer.push(Survey {
-                            resource,
-                            dx,
-                            dy,
-                            found: false,
-                        });
-
-                        // Last-N semantics: keep only last batch_size entries
-                        let batch = s.batch_size;
-                        if s.record_buffer.len() > batch {
-                            let excess = s.record_buffer.len() - batch;
-                            s.record_buffer.drain(..excess);
-                        }
+                state_changed = true;
 
-                        state_changed = true;
-
-                        // Auto-stop when we hit batch_size
-                        if s.record_buffer.len() == batch {
-                            // Move buffer into surveys, deduplicate, switch to Find
-                            s.surveys = deduplicate_surveys(&s.record_buffer);
-                            s.record_buffer.clear();
-                            s.path_order = pathfinder::find_path(
-                                s.player_pos,
-                                &s.surveys,
-                                &s.zone,
-                            );
-                            s.mode = Mode::Find;
-                        }
-                    }
+                // Auto-stop when we hit batch_size
+                if s.record_buffer.len() == batch {
+                    // Move buffer into surveys, deduplicate, switch to Find
+                    let buffered = std::mem::take(&mut s.record_buffer);
+                    s.start_batch(&buffered);
+                    fired.push(batch_recorded(s));
+                }
+            }
+            // Readings taken before the batch is done queue up for the next one
+            (ChatLine::Survey(survey), Mode::Find) => {
+                s.log_reading(&survey, source);
+                let pos = s.player_pos;
+                s.queued.push(QueuedReading { survey, pos });
+                let batch = s.batch_size;
+                if s.queued.len() > batch {
+                    let excess = s.queued.len() - batch;
+                    s.queued.drain(..excess);
                 }
-                Mode::Find => {
-                    // Only respond to "collected!" lines
-                    if let Some(_caps) = collected_re.captures(line) {
-                        // Sequential path-order matching: find next unvisited in path order
-                        let next = s
-                            .path_order
-                            .iter()
-                            .find(|&&idx| idx < s.surveys.len() && !s.surveys[idx].found)
-                            .copied();
-                        if let Some(idx) = next {
-                            s.surveys[idx].found = true;
-                            state_changed = true;
+                state_changed = true;
+            }
+            // Only respond to "collected!" lines in Find mode
+            (ChatLine::Collected(item), Mode::Find) => {
+                if let Some(idx) = s.survey_for_item(&item) {
+                    // We're standing where the node was; route on from here
+                    let survey = &s.surveys[idx];
+                    fired.push(Fired::new(
+                        HookEvent::SurveyCollected,
+                        &s.zone,
+                        json!({
+                            "index": idx,
+                            "resource": survey.resource,
+                            "dx": survey.dx,
+                            "dy": survey.dy,
+                            "item": item,
+                        }),
+                    ));
+                    s.collect(idx, item, source);
+                    if let Some(batch) = s.complete_batch() {
+                        fired.push(Fired::new(
+                            HookEvent::BatchComplete,
+                            &batch.zone,
+                            json!(batch),
+                        ));
+                        // A full queue goes straight into the next batch
+                        if s.mode == Mode::Find {
+                            fired.push(batch_recorded(s));
                         }
+                        completed.push(batch);
                     }
-                    // Location lines are ignored in Find mode
+                    state_changed = true;
+                } else {
+                    s.log_collection(&item, None, source);
                 }
             }
-        }
-
-        // Recompute path if state changed and in Find mode
-        if state_changed && s.mode == Mode::Find {
-            s.path_order =
-                pathfinder::find_path(s.player_pos, &s.surveys, &s.zone);
+            (ChatLine::Collected(item), Mode::Record) => s.log_collection(&item, None, source),
+            _ => {}
         }
     }
 
-    if zone_changed {
-        let _ = app.emit("zone-changed", ());
+    // Recompute path if state changed and in Find mode
+    if state_changed && s.mode == Mode::Find {
+        s.reroute();
     }
 
     if state_changed {
-        let s = state.lock().unwrap();
-        let payload = compute_render_payload(&s);
-        let _ = app.emit("state-updated", payload);
+        s.history.record(before);
+    }
+
+    LinesRead {
+        state_changed,
+        zone_changed,
+        completed,
+        fired,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::SurveyStatus;
+
+    const LOG: &str = "\
+26-01-02 14:02:50\t******** Entering Area: Eltibule
+26-01-02 14:03:11\t[Status] The Copper Ore is 10m east and 20m north.
+26-01-02 14:03:15\t[Status] The Tin Ore is 30m west and 5m south.
+26-01-02 14:03:20\t[Global] Someone: anyone selling Tin Ore?
+26-01-02 14:04:02\t[Status] Tin Ore collected!
+";
+
+    fn read(s: &mut AppState, lines: &str) -> LinesRead {
+        apply_lines(
+            s,
+            &ChatParser::new(),
+            "Chat-26-01-02.log",
+            0,
+            lines.as_bytes(),
+        )
+    }
+
+    fn events(read: &LinesRead) -> Vec<HookEvent> {
+        read.fired.iter().map(|f| f.event).collect()
+    }
+
+    #[test]
+    fn log_lines_record_a_batch_and_collect_from_it() {
+        let mut s = AppState {
+            batch_size: 2,
+            ..Default::default()
+        };
+        let first = read(&mut s, LOG);
+        assert!(first.state_changed && first.zone_changed);
+        assert_eq!(
+            events(&first),
+            [
+                HookEvent::ZoneChanged,
+                HookEvent::BatchRecorded,
+                HookEvent::SurveyCollected
+            ]
+        );
+        assert_eq!(s.zone, "Eltibule");
+        assert_eq!(s.mode, Mode::Find);
+        let tin = s
+            .surveys
+            .iter()
+            .position(|v| v.resource == "Tin Ore")
+            .unwrap();
+        assert_eq!(s.surveys[tin].status, SurveyStatus::Found);
+        assert!(s.history.can_undo());
+
+        // An item named differently from its resource is the next target's
+        let second = read(
+            &mut s,
+            "26-01-02 14:05:40\t[Status] Native Copper collected!\n",
+        );
+        assert_eq!(
+            events(&second),
+            [HookEvent::SurveyCollected, HookEvent::BatchComplete]
+        );
+        assert_eq!(second.completed.len(), 1);
+        assert_eq!(
+            second.completed[0].items_collected,
+            ["Tin Ore", "Native Copper"]
+        );
+    }
+
+    #[test]
+    fn collections_while_recording_change_nothing() {
+        let mut s = AppState::default();
+        let read = read(&mut s, "26-01-02 14:04:02\t[Status] Tin Ore collected!\n");
+        assert!(!read.state_changed);
+        assert!(read.fired.is_empty());
+        assert!(!s.history.can_undo());
     }
 }
@@ -13,6 +13,19 @@ const dialogOpen = () => {
   return null;
 };
 
+const dialogSave = () => {
+  const api = window.__TAURI_PLUGIN_DIALOG__;
+  if (api && api.save) return api.save;
+  if (window.__TAURI__.dialog) return window.__TAURI__.dialog.save;
+  return null;
+};
+
+const EXPORT_FORMATS = {
+  csv:   { name: 'CSV', extensions: ['csv'] },
+  json:  { name: 'JSON', extensions: ['json'] },
+  route: { name: 'Route', extensions: ['gpx'] },
+};
+
 const ZONE_MAPS = {
   'Serbule':       'assets/serbule_map.webp',
   'Serbule Hills': 'assets/serbule_hills_map.webp',
@@ -27,7 +40,10 @@ const mapImg = () => $('#zone-map');
 const pathCanvas = () => $('#path-canvas');
 
 let currentPayload = null;
+let heatmap = null;
 let dragging = false;
+let draggingMember = 0; // 0 = local player, 1.. = other group members
+let draggingOrigin = false;
 
 // ── Initialization ──────────────────────────────────────────────────
 document.addEventListener('DOMContentLoaded', async () => {
@@ -36,10 +52,19 @@ document.addEventListener('DOMContentLoaded', async () => {
     render(event.payload);
   });
 
+  await listen('batch-completed', (event) => {
+    refreshHeatmap();
+    const b = event.payload;
+    const found = b.route.length;
+    $('#archive-last').textContent =
+      `Last: ${found}/${b.surveys.length} found, ${Math.round(b.distance_walked_m)}m walked`;
+  });
+
   await listen('zone-changed', async () => {
     const payload = await invoke('get_render_state');
     updateZoneMap(payload.zone);
     render(payload);
+    refreshHeatmap();
   });
 
   // Mode toggle
@@ -58,6 +83,7 @@ document.addEventListener('DOMContentLoaded', async () => {
     const payload = await invoke('set_zone', { zone: e.target.value });
     updateZoneMap(e.target.value);
     render(payload);
+    refreshHeatmap();
   });
 
   // Batch size
@@ -66,12 +92,169 @@ document.addEventListener('DOMContentLoaded', async () => {
     await invoke('set_batch_size', { n });
   });
 
+  // Record-mode batch controls
+  $('#finish-batch-btn').addEventListener('click', () => recordCommand('finish_batch'));
+  $('#discard-last-btn').addEventListener('click', () => recordCommand('discard_last_reading'));
+  $('#extend-batch-btn').addEventListener('click', () => recordCommand('extend_batch', { by: 1 }));
+
+  // Reroute margin
+  $('#reroute-margin').addEventListener('change', async (e) => {
+    const pct = parseFloat(e.target.value);
+    const margin = (isNaN(pct) ? 10 : pct) / 100;
+    render(await invoke('set_reroute_margin', { margin }));
+  });
+
+  // Move the survey spot so every dot fits in the zone
+  $('#bounds-fix-btn').addEventListener('click', async () => {
+    if (!currentPayload || !currentPayload.suggested_origin) return;
+    const [x, y] = currentPayload.suggested_origin;
+    // Before walking anywhere the player is standing on the survey spot
+    const cmd = currentPayload.trail.length === 0 ? 'set_player_pos' : 'set_survey_origin';
+    render(await invoke(cmd, { x, y }));
+  });
+
+  // Accept the inferred survey position
+  $('#estimate-accept-btn').addEventListener('click', async () => {
+    try {
+      render(await invoke('accept_position_estimate'));
+    } catch (e) {
+      console.error('accept_position_estimate failed:', e);
+    }
+  });
+
+  // Group members
+  $('#add-member-btn').addEventListener('click', async () => {
+    const payload = await invoke('add_group_member', { x: 0.5, y: 0.5 });
+    render(payload);
+  });
+
   // Clear
   $('#clear-btn').addEventListener('click', async () => {
     const payload = await invoke('clear_surveys');
     render(payload);
   });
 
+  // Manual survey entry
+  $('#survey-add').addEventListener('submit', async (e) => {
+    e.preventDefault();
+    const dx = parseInt($('#add-dx').value, 10);
+    const dy = parseInt($('#add-dy').value, 10);
+    if (isNaN(dx) || isNaN(dy)) {
+      showSurveyError('Enter both offsets in meters');
+      return;
+    }
+    await surveyCommand('add_survey', {
+      resource: $('#add-resource').value,
+      placement: { kind: 'offset', dx, dy },
+    });
+  });
+
+  mapContainer().addEventListener('click', async (e) => {
+    if (!e.altKey) return;
+    const rect = mapImg().getBoundingClientRect();
+    await surveyCommand('add_survey', {
+      resource: $('#add-resource').value,
+      placement: {
+        kind: 'point',
+        x: (e.clientX - rect.left) / rect.width,
+        y: (e.clientY - rect.top) / rect.height,
+      },
+    });
+  });
+
+  // Paste chat text
+  $('#paste-btn').addEventListener('click', async () => {
+    const text = $('#paste-text').value;
+    if (!text.trim()) return;
+    const { report, payload } = await invoke('import_chat_text', { text });
+    const kinds = {};
+    for (const r of report.recognized) kinds[r.kind] = (kinds[r.kind] || 0) + 1;
+    const parts = Object.entries(kinds).map(([k, n]) => `${n} ${k}`);
+    $('#paste-status').textContent =
+      `Recognized ${parts.join(', ') || 'nothing'}; ignored ${report.ignored.length} line(s).`;
+    $('#paste-status').title = report.ignored.join('\n');
+    render(payload);
+  });
+
+  // Spawn heatmap
+  $('#heatmap-select').addEventListener('change', refreshHeatmap);
+
+  // Share codes
+  $('#share-copy-btn').addEventListener('click', async () => {
+    const code = await invoke('get_share_code');
+    $('#share-code').value = code;
+    try {
+      await navigator.clipboard.writeText(code);
+      $('#share-status').textContent = 'Copied to clipboard.';
+    } catch {
+      $('#share-status').textContent = 'Select the code above to copy it.';
+    }
+  });
+  $('#share-merge-btn').addEventListener('click', () => importShareCode(true));
+  $('#share-replace-btn').addEventListener('click', () => importShareCode(false));
+
+  // Export
+  document.querySelectorAll('[data-export]').forEach(btn => {
+    btn.addEventListener('click', () => exportSession(btn.dataset.export));
+  });
+
+  $('#save-image-btn').addEventListener('click', saveImage);
+  $('#import-session-btn').addEventListener('click', importSession);
+
+  // Long-term history
+  $('#history-by').addEventListener('change', loadHistoryTotals);
+  $('#history-refresh-btn').addEventListener('click', loadHistoryTotals);
+  loadHistoryTotals();
+
+  // Global hotkeys
+  renderHotkeys(await invoke('get_hotkeys'));
+
+  // Live view server
+  renderServerStatus(await invoke('get_server_status'));
+  ['#server-enabled', '#server-lan', '#server-commands', '#server-port'].forEach(id => {
+    $(id).addEventListener('change', applyServerSettings);
+  });
+
+  // Group sync
+  renderSyncStatus(await invoke('get_sync_status'));
+  ['#sync-enabled', '#sync-port', '#sync-peers', '#sync-key'].forEach(id => {
+    $(id).addEventListener('change', applySyncSettings);
+  });
+  // Peers come and go without the state changing
+  setInterval(async () => {
+    if ($('#sync-enabled').checked && !$('#sync-status').classList.contains('error')) {
+      renderSyncStatus(await invoke('get_sync_status'));
+    }
+  }, 5000);
+
+  // Event hooks
+  renderHooks(await invoke('get_hooks'));
+  $('#add-hook-btn').addEventListener('click', () => {
+    hooks.push({ event: 'survey_collected', action: { kind: 'command', command: '' }, timeout_ms: 5000 });
+    renderHooks(hooks);
+  });
+
+  // Always-on-top overlay
+  $('#overlay-btn').addEventListener('click', async () => {
+    const visible = await invoke('toggle_overlay');
+    $('#overlay-btn').classList.toggle('active', visible);
+  });
+
+  // Undo / redo
+  $('#undo-btn').addEventListener('click', async () => render(await invoke('undo')));
+  $('#redo-btn').addEventListener('click', async () => render(await invoke('redo')));
+  document.addEventListener('keydown', async (e) => {
+    if (!(e.ctrlKey || e.metaKey) || e.target.tagName === 'INPUT') return;
+    const key = e.key.toLowerCase();
+    if (key === 'z') {
+      e.preventDefault();
+      render(await invoke(e.shiftKey ? 'redo' : 'undo'));
+    } else if (key === 'y') {
+      e.preventDefault();
+      render(await invoke('redo'));
+    }
+  });
+
   // Browse for log directory
   $('#browse-btn').addEventListener('click', async () => {
     const openFn = dialogOpen();
@@ -129,9 +312,12 @@ function updateZoneMap(zone) {
 // ── Player dragging ─────────────────────────────────────────────────
 function onPointerDown(e) {
   const target = e.target;
-  if (!target.classList.contains('player-icon')) return;
+  const isOrigin = target.classList.contains('origin-icon');
+  if (!target.classList.contains('player-icon') && !isOrigin) return;
   e.preventDefault();
   dragging = true;
+  draggingOrigin = isOrigin;
+  draggingMember = parseInt(target.dataset.member || '0', 10);
   target.setPointerCapture(e.pointerId);
 }
 
@@ -141,10 +327,17 @@ function onPointerMove(e) {
   const rect = img.getBoundingClientRect();
   const x = (e.clientX - rect.left) / rect.width;
   const y = (e.clientY - rect.top) / rect.height;
-  invoke('set_player_pos', {
+  const pos = {
     x: Math.max(0, Math.min(1, x)),
     y: Math.max(0, Math.min(1, y)),
-  }).then(render);
+  };
+  if (draggingOrigin) {
+    invoke('set_survey_origin', pos).then(render);
+  } else if (draggingMember > 0) {
+    invoke('set_group_member_pos', { member: draggingMember, ...pos }).then(render);
+  } else {
+    invoke('set_player_pos', pos).then(render);
+  }
 }
 
 function onPointerUp() {
@@ -163,19 +356,76 @@ function render(payload) {
 
   // Zone display
   $('#zone-display').textContent = payload.zone;
+  if ($('#zone-select').value !== payload.zone) updateZoneMap(payload.zone);
+
+  // Undo / redo availability
+  $('#undo-btn').disabled = !payload.can_undo;
+  $('#redo-btn').disabled = !payload.can_redo;
 
   // Summary
   $('#result-summary').textContent = payload.summary;
 
+  // Record progress
+  const rec = payload.record;
+  $('#record-row').hidden = !isRecord;
+  $('#record-progress').textContent = `${rec.buffered.length}/${rec.batch_size} recorded`;
+  $('#record-buffer').textContent = rec.buffered
+    .map(b => `${b.resource} (${b.dx}, ${b.dy})`)
+    .join(' · ');
+  $('#finish-batch-btn').disabled = rec.buffered.length === 0;
+  $('#discard-last-btn').disabled = rec.buffered.length === 0;
+  if (document.activeElement !== $('#batch-size')) $('#batch-size').value = rec.batch_size;
+
+  // Next batch, queued while finding this one
+  $('#queue-row').hidden = isRecord || payload.queued.length === 0;
+  $('#queue-progress').textContent = `Next batch: ${payload.queued.length}/${rec.batch_size} queued`;
+  $('#queue-buffer').textContent = payload.queued.map(q => q.resource).join(' · ');
+
+  // Turn-by-turn
+  $('#next-leg-row').hidden = !payload.next_leg;
+  $('#next-leg').textContent = payload.next_leg ? payload.next_leg.text : '';
+  refreshDirections(payload);
+
+  // Archived batches
+  $('#archive-row').hidden = payload.completed_batches === 0;
+  $('#archive-count').textContent =
+    `${payload.completed_batches} batch${payload.completed_batches === 1 ? '' : 'es'} completed`;
+
+  // Out-of-bounds warning
+  const warnEl = $('#bounds-warning');
+  const oob = payload.dots.filter(d => d.out_of_bounds).length;
+  warnEl.hidden = oob === 0;
+  $('#bounds-warning-text').textContent =
+    `${oob} survey${oob === 1 ? '' : 's'} outside the zone — check the player marker.`;
+  $('#bounds-fix-btn').hidden = !payload.suggested_origin;
+
+  // Inferred survey position
+  const est = payload.position_estimate;
+  $('#estimate-row').hidden = !est;
+  if (est) {
+    $('#estimate-text').textContent =
+      `Surveys suggest you stood elsewhere (${Math.round(est.confidence * 100)}% confident).`;
+  }
+
   // Resource pills
   const pillsEl = $('#resource-pills');
-  pillsEl.innerHTML = payload.resources
-    .map(r => `<span class="resource-pill"><span class="count">${r.count}</span>${r.name}</span>`)
-    .join('');
+  pillsEl.innerHTML = '';
+  for (const r of payload.resources) {
+    const pill = document.createElement('span');
+    pill.className = r.excluded ? 'resource-pill excluded' : 'resource-pill';
+    pill.title = 'Click to include/exclude from route';
+    const count = document.createElement('span');
+    count.className = 'count';
+    count.textContent = r.count;
+    // Names can come from share codes and peers; never parse them as HTML
+    pill.append(count, r.name);
+    pill.addEventListener('click', () => onResourceClick(r.name));
+    pillsEl.append(pill);
+  }
 
   // Clear existing dots and player icon
   const mc = mapContainer();
-  mc.querySelectorAll('.survey-dot, .survey-label, .player-icon').forEach(el => el.remove());
+  mc.querySelectorAll('.survey-dot, .survey-label, .player-icon, .origin-icon, .estimate-icon').forEach(el => el.remove());
 
   // Player icon
   const player = document.createElement('div');
@@ -187,35 +437,461 @@ function render(payload) {
   player.style.top = (payload.player_pos[1] * imgH) + 'px';
   mc.appendChild(player);
 
+  // Survey spot, once the player has walked away from it
+  if (payload.trail.length > 0) {
+    const origin = document.createElement('div');
+    origin.className = 'origin-icon';
+    origin.title = 'Survey spot (drag to re-anchor dots)';
+    origin.style.left = (payload.survey_origin[0] * imgW) + 'px';
+    origin.style.top = (payload.survey_origin[1] * imgH) + 'px';
+    mc.appendChild(origin);
+  }
+
+  // Estimated survey spot
+  if (est) {
+    const ghost = document.createElement('div');
+    ghost.className = 'estimate-icon';
+    ghost.title = `Estimated survey spot (${Math.round(est.confidence * 100)}%)`;
+    ghost.style.left = (est.pos[0] * imgW) + 'px';
+    ghost.style.top = (est.pos[1] * imgH) + 'px';
+    mc.appendChild(ghost);
+  }
+
+  // Other group members (member 0 is the local player above)
+  for (const route of payload.routes.slice(1)) {
+    const member = document.createElement('div');
+    member.className = 'player-icon member';
+    member.dataset.member = route.member;
+    member.title = `Member ${route.member + 1} — ${Math.round(route.distance_m)}m (right-click to remove)`;
+    member.style.background = route.color;
+    member.style.left = (route.start[0] * imgW) + 'px';
+    member.style.top = (route.start[1] * imgH) + 'px';
+    member.addEventListener('contextmenu', async (e) => {
+      e.preventDefault();
+      render(await invoke('remove_group_member', { member: route.member }));
+    });
+    mc.appendChild(member);
+  }
+
   // Survey dots
   for (let i = 0; i < payload.dots.length; i++) {
     const dot = payload.dots[i];
 
+    // Out-of-bounds dots are pinned to the map edge for display only
+    const x = Math.max(0, Math.min(imgW, dot.x));
+    const y = Math.max(0, Math.min(imgH, dot.y));
+
     const dotEl = document.createElement('span');
-    dotEl.className = 'survey-dot' + (dot.found ? ' found' : '');
-    dotEl.style.left = dot.x + 'px';
-    dotEl.style.top = dot.y + 'px';
-    dotEl.addEventListener('click', () => onDotClick(i));
+    const statusClass = dot.status === 'pending' ? '' : ' ' + dot.status;
+    dotEl.className = 'survey-dot' + statusClass + (dot.excluded ? ' excluded' : '') + (dot.next ? ' next' : '') + (dot.out_of_bounds ? ' out-of-bounds' : '');
+    dotEl.style.left = x + 'px';
+    dotEl.style.top = y + 'px';
+    if (dot.out_of_bounds) dotEl.title = 'Outside the zone — is the player marker in the right place?';
+    dotEl.addEventListener('click', (e) => onDotClick(e, i, dot));
+    dotEl.addEventListener('contextmenu', (e) => onDotSkip(e, i, dot));
     mc.appendChild(dotEl);
 
     const lbl = document.createElement('span');
-    lbl.className = 'survey-label' + (dot.found ? ' found' : '');
+    lbl.className = 'survey-label' + statusClass + (dot.excluded ? ' excluded' : '');
     lbl.textContent = dot.label;
-    lbl.style.left = (dot.x + 8) + 'px';
-    lbl.style.top = (dot.y - 10) + 'px';
-    lbl.addEventListener('click', () => onDotClick(i));
+    if (payload.routes.length > 1 && dot.member !== null && dot.status === 'pending') {
+      lbl.style.color = payload.routes[dot.member].color;
+    }
+    lbl.style.left = (x + 8) + 'px';
+    lbl.style.top = (y - 10) + 'px';
+    lbl.addEventListener('click', (e) => onDotClick(e, i, dot));
+    lbl.addEventListener('contextmenu', (e) => onDotSkip(e, i, dot));
     mc.appendChild(lbl);
   }
 
+  renderSurveyList(payload);
+
   // Path canvas
   drawPath(payload);
 }
 
-async function onDotClick(index) {
+// Click toggles found; shift-click toggles unreachable
+async function onDotClick(e, index, dot) {
+  if (e.shiftKey) {
+    const status = dot.status === 'unreachable' ? 'pending' : 'unreachable';
+    render(await invoke('set_survey_status', { index, status }));
+    return;
+  }
   const payload = await invoke('toggle_found', { index });
   render(payload);
 }
 
+// Right-click toggles skipped
+async function onDotSkip(e, index, dot) {
+  e.preventDefault();
+  const status = dot.status === 'skipped' ? 'pending' : 'skipped';
+  render(await invoke('set_survey_status', { index, status }));
+}
+
+async function recordCommand(cmd, args = {}) {
+  try {
+    render(await invoke(cmd, args));
+  } catch (e) {
+    console.error(cmd + ' failed:', e);
+  }
+}
+
+async function onResourceClick(name) {
+  try {
+    const payload = await invoke('toggle_resource', { name });
+    render(payload);
+  } catch (e) {
+    console.error('toggle_resource failed:', e);
+  }
+}
+
+// ── Survey list ─────────────────────────────────────────────────────
+async function importShareCode(merge) {
+  const code = $('#share-code').value.trim();
+  if (!code) return;
+  try {
+    const { added, payload } = await invoke('import_share_code', { code, merge });
+    updateZoneMap(payload.zone);
+    render(payload);
+    $('#share-status').textContent = `Loaded ${added} survey${added === 1 ? '' : 's'}.`;
+  } catch (e) {
+    $('#share-status').textContent = String(e);
+  }
+}
+
+async function refreshDirections(payload) {
+  const legs = payload.mode === 'find' ? await invoke('get_directions') : [];
+  const list = $('#directions-list');
+  list.innerHTML = '';
+  for (const leg of legs) {
+    const item = document.createElement('li');
+    item.textContent = leg.text;
+    list.append(item);
+  }
+  $('#directions-empty').hidden = legs.length > 0;
+}
+
+// Name and suggested key per action; Clear has no suggestion so it's
+// never bound by accident
+const HOTKEY_ACTIONS = {
+  toggle_found: ['Mark found', 'e.g. Ctrl+Shift+F1'],
+  skip: ['Skip', 'e.g. Ctrl+Shift+F2'],
+  toggle_mode: ['Switch mode', 'e.g. Ctrl+Shift+F3'],
+  clear: ['Clear', 'Not bound'],
+};
+
+function renderHotkeys(status) {
+  const { bindings, error } = status;
+  const list = $('#hotkey-list');
+  list.innerHTML = '';
+  for (const [action, [name, hint]] of Object.entries(HOTKEY_ACTIONS)) {
+    const row = document.createElement('label');
+    row.className = 'hotkey-row';
+    row.textContent = name;
+    const input = document.createElement('input');
+    input.type = 'text';
+    input.value = bindings[action] || '';
+    input.placeholder = hint;
+    input.addEventListener('change', async () => {
+      try {
+        renderHotkeys(await invoke('set_hotkey', { action, accelerator: input.value || null }));
+        $('#hotkey-status').textContent = 'Saved.';
+        $('#hotkey-status').classList.remove('error');
+      } catch (e) {
+        input.value = bindings[action] || '';
+        $('#hotkey-status').textContent = String(e);
+        $('#hotkey-status').classList.add('error');
+      }
+    });
+    row.append(input);
+    list.append(row);
+  }
+  if (error) {
+    $('#hotkey-status').textContent = error;
+    $('#hotkey-status').classList.add('error');
+  }
+}
+
+function renderServerStatus(status) {
+  const { settings, url } = status;
+  $('#server-enabled').checked = settings.enabled;
+  $('#server-lan').checked = settings.lan;
+  $('#server-commands').checked = settings.allow_commands;
+  $('#server-port').value = settings.port;
+  if (!url) {
+    $('#server-status').textContent = 'Off.';
+  } else if (settings.allow_commands) {
+    $('#server-status').textContent = `Open ${url} — anyone with this link can mark surveys.`;
+  } else {
+    $('#server-status').textContent = `Open ${url}`;
+  }
+  $('#server-status').classList.remove('error');
+}
+
+async function applyServerSettings() {
+  const settings = {
+    enabled: $('#server-enabled').checked,
+    lan: $('#server-lan').checked,
+    allow_commands: $('#server-commands').checked,
+    port: parseInt($('#server-port').value, 10) || 7878,
+  };
+  try {
+    renderServerStatus(await invoke('set_server_settings', { settings }));
+  } catch (e) {
+    renderServerStatus(await invoke('get_server_status'));
+    $('#server-status').textContent = String(e);
+    $('#server-status').classList.add('error');
+  }
+}
+
+function renderSyncStatus(status) {
+  const { settings, port, connected } = status;
+  $('#sync-enabled').checked = settings.enabled;
+  $('#sync-port').value = settings.port;
+  if (document.activeElement !== $('#sync-peers')) {
+    $('#sync-peers').value = settings.peers.join(', ');
+  }
+  if (document.activeElement !== $('#sync-key')) {
+    $('#sync-key').value = settings.key;
+  }
+  if (port == null && settings.enabled && !settings.key) {
+    $('#sync-status').textContent = 'Set a group key to sync.';
+  } else if (port == null) {
+    $('#sync-status').textContent = 'Off.';
+  } else if (connected.length) {
+    $('#sync-status').textContent = `Port ${port}. Connected: ${connected.join(', ')}`;
+  } else {
+    $('#sync-status').textContent = `Port ${port}. Waiting for peers…`;
+  }
+  $('#sync-status').classList.remove('error');
+}
+
+async function applySyncSettings() {
+  const settings = {
+    enabled: $('#sync-enabled').checked,
+    port: parseInt($('#sync-port').value, 10) || 7879,
+    peers: $('#sync-peers').value.split(/[,\s]+/).filter(Boolean),
+    key: $('#sync-key').value.trim(),
+  };
+  try {
+    renderSyncStatus(await invoke('set_sync_settings', { settings }));
+  } catch (e) {
+    renderSyncStatus(await invoke('get_sync_status'));
+    $('#sync-status').textContent = String(e);
+    $('#sync-status').classList.add('error');
+  }
+}
+
+const HOOK_EVENTS = {
+  batch_recorded: 'Batch recorded',
+  survey_collected: 'Survey collected',
+  batch_complete: 'Batch complete',
+  zone_changed: 'Zone changed',
+};
+
+let hooks = [];
+
+function hookStatus(text, isError) {
+  $('#hook-status').textContent = text;
+  $('#hook-status').classList.toggle('error', isError);
+}
+
+// Hooks with no command/URL yet are kept on screen but not saved
+async function saveHooks() {
+  const ready = hooks.filter(h => (h.action.command ?? h.action.url ?? '').trim());
+  try {
+    await invoke('set_hooks', { hooks: ready });
+    hookStatus('Saved.', false);
+  } catch (e) {
+    hookStatus(String(e), true);
+  }
+}
+
+function renderHooks(list) {
+  hooks = list;
+  const container = $('#hook-list');
+  container.innerHTML = '';
+  hooks.forEach((hook, i) => {
+    const row = document.createElement('div');
+    row.className = 'hook-row';
+
+    const event = document.createElement('select');
+    for (const [value, name] of Object.entries(HOOK_EVENTS)) {
+      event.append(new Option(name, value, false, value === hook.event));
+    }
+    event.addEventListener('change', () => { hook.event = event.value; saveHooks(); });
+
+    const kind = document.createElement('select');
+    kind.append(new Option('Run', 'command', false, hook.action.kind === 'command'));
+    kind.append(new Option('POST', 'post', false, hook.action.kind === 'post'));
+    kind.addEventListener('change', () => {
+      hook.action = kind.value === 'post' ? { kind: 'post', url: '' } : { kind: 'command', command: '' };
+      renderHooks(hooks);
+    });
+
+    const target = document.createElement('input');
+    target.type = 'text';
+    const field = hook.action.kind === 'post' ? 'url' : 'command';
+    target.value = hook.action[field];
+    target.placeholder = field === 'url' ? 'http://localhost:8080/hook' : 'e.g. python log.py';
+    target.addEventListener('change', () => { hook.action[field] = target.value.trim(); saveHooks(); });
+
+    const test = document.createElement('button');
+    test.className = 'btn btn-small btn-secondary';
+    test.type = 'button';
+    test.textContent = 'Test';
+    test.addEventListener('click', async () => {
+      hookStatus('Running…', false);
+      try {
+        await invoke('test_hook', { hook });
+        hookStatus('Hook ran fine.', false);
+      } catch (e) {
+        hookStatus(String(e), true);
+      }
+    });
+
+    const remove = document.createElement('button');
+    remove.className = 'btn btn-small btn-secondary';
+    remove.type = 'button';
+    remove.textContent = '×';
+    remove.title = 'Remove hook';
+    remove.addEventListener('click', () => {
+      hooks.splice(i, 1);
+      renderHooks(hooks);
+      saveHooks();
+    });
+
+    row.append(event, kind, target, test, remove);
+    container.append(row);
+  });
+}
+
+async function exportSession(format) {
+  const saveFn = dialogSave();
+  if (!saveFn) {
+    $('#export-status').textContent = 'Error: dialog plugin not available';
+    return;
+  }
+  const filter = EXPORT_FORMATS[format];
+  const path = await saveFn({
+    title: `Export ${filter.name}`,
+    defaultPath: `survey-session.${filter.extensions[0]}`,
+    filters: [filter],
+  });
+  if (!path) return;
+  try {
+    await invoke('export_session', { path, format });
+    $('#export-status').textContent = `Saved ${path}`;
+  } catch (e) {
+    $('#export-status').textContent = `Export failed: ${e}`;
+  }
+}
+
+async function saveImage() {
+  const saveFn = dialogSave();
+  if (!saveFn) {
+    $('#export-status').textContent = 'Error: dialog plugin not available';
+    return;
+  }
+  const path = await saveFn({
+    title: 'Save image',
+    defaultPath: 'survey-route.png',
+    filters: [{ name: 'PNG', extensions: ['png'] }, { name: 'SVG', extensions: ['svg'] }],
+  });
+  if (!path) return;
+  try {
+    await invoke('render_image', { path });
+    $('#export-status').textContent = `Saved ${path}`;
+  } catch (e) {
+    $('#export-status').textContent = `Image failed: ${e}`;
+  }
+}
+
+async function importSession() {
+  const openFn = dialogOpen();
+  if (!openFn) {
+    $('#export-status').textContent = 'Error: dialog plugin not available';
+    return;
+  }
+  const path = await openFn({ title: 'Import session', filters: [EXPORT_FORMATS.json] });
+  if (!path) return;
+  try {
+    const payload = await invoke('import_session', { path });
+    updateZoneMap(payload.zone);
+    render(payload);
+    $('#export-status').textContent = `Loaded ${path}`;
+  } catch (e) {
+    $('#export-status').textContent = `Import failed: ${e}`;
+  }
+}
+
+async function loadHistoryTotals() {
+  const body = $('#history-table tbody');
+  body.innerHTML = '';
+  let totals;
+  try {
+    totals = await invoke('get_history_totals', { by: $('#history-by').value });
+  } catch (e) {
+    $('#history-status').textContent = String(e);
+    return;
+  }
+  for (const t of totals) {
+    const row = document.createElement('tr');
+    for (const v of [t.key, t.readings, t.collected]) {
+      const cell = document.createElement('td');
+      cell.textContent = v;
+      row.append(cell);
+    }
+    body.append(row);
+  }
+  $('#history-status').textContent = totals.length === 0 ? 'Nothing recorded yet.' : '';
+}
+
+function renderSurveyList(payload) {
+  const list = $('#survey-list');
+  list.innerHTML = '';
+  payload.dots.forEach((dot, index) => {
+    const row = document.createElement('div');
+    row.className = 'survey-row' + (dot.status === 'pending' ? '' : ' ' + dot.status);
+
+    const field = (type, value, key) => {
+      const input = document.createElement('input');
+      input.type = type;
+      input.value = value;
+      input.addEventListener('change', () => {
+        const v = type === 'number' ? parseInt(input.value, 10) : input.value;
+        surveyCommand('edit_survey', { index, [key]: v });
+      });
+      return input;
+    };
+
+    const del = document.createElement('button');
+    del.type = 'button';
+    del.className = 'survey-delete';
+    del.textContent = '\u00d7';
+    del.title = 'Delete survey';
+    del.addEventListener('click', () => surveyCommand('delete_survey', { index }));
+
+    row.append(field('text', dot.resource, 'resource'), field('number', dot.dx, 'dx'), field('number', dot.dy, 'dy'), del);
+    list.appendChild(row);
+  });
+}
+
+async function surveyCommand(cmd, args) {
+  try {
+    render(await invoke(cmd, args));
+    showSurveyError('');
+  } catch (e) {
+    showSurveyError(String(e));
+  }
+}
+
+function showSurveyError(msg) {
+  const el = $('#survey-error');
+  el.textContent = msg || 'Alt-click the map to add the resource at that spot.';
+  el.classList.toggle('error', !!msg);
+}
+
 // ── Path drawing ────────────────────────────────────────────────────
 function drawPath(payload) {
   const canvas = pathCanvas();
@@ -226,25 +902,122 @@ function drawPath(payload) {
   const ctx = canvas.getContext('2d');
   ctx.clearRect(0, 0, canvas.width, canvas.height);
 
-  if (payload.path_indices.length < 2) return;
+  drawHeatmap(ctx, canvas.width, canvas.height);
+  drawTrail(ctx, payload, canvas.width, canvas.height);
+  drawMotherlodes(ctx, payload, canvas.width, canvas.height);
+
+  const solo = payload.routes.length === 1;
+  for (const route of payload.routes) {
+    if (solo && route.path_indices.length < 2) return;
+
+    ctx.strokeStyle = solo ? '#7fbbb3' : route.color;
+    ctx.lineWidth = 1.5;
+    ctx.setLineDash([4, 4]);
+    ctx.globalAlpha = 0.6;
+    ctx.beginPath();
+
+    // Start from this member's position
+    const px = route.start[0] * canvas.width;
+    const py = route.start[1] * canvas.height;
+    ctx.moveTo(px, py);
+
+    for (const idx of route.path_indices) {
+      const dot = payload.dots[idx];
+      if (dot && dot.status === 'pending') {
+        ctx.lineTo(dot.x, dot.y);
+      }
+    }
 
-  ctx.strokeStyle = '#7fbbb3';
-  ctx.lineWidth = 1.5;
-  ctx.setLineDash([4, 4]);
-  ctx.globalAlpha = 0.6;
-  ctx.beginPath();
+    ctx.stroke();
+  }
+}
 
-  // Start from player position
-  const px = payload.player_pos[0] * canvas.width;
-  const py = payload.player_pos[1] * canvas.height;
-  ctx.moveTo(px, py);
+// Fetch the density grid for the selected resource (or clear it when off)
+async function refreshHeatmap() {
+  const select = $('#heatmap-select');
+  heatmap = select.value === 'off'
+    ? null
+    : await invoke('get_heatmap', { resource: select.value || null });
+
+  if (heatmap) {
+    const keep = new Set(['off', '']);
+    for (const opt of [...select.options]) if (!keep.has(opt.value)) opt.remove();
+    const names = new Set(heatmap.resources);
+    if (heatmap.resource) names.add(heatmap.resource);
+    for (const name of names) select.add(new Option(`Heatmap: ${name}`, name));
+    select.value = heatmap.resource ?? '';
+  }
+  if (currentPayload) drawPath(currentPayload);
+}
 
-  for (const idx of payload.path_indices) {
-    const dot = payload.dots[idx];
-    if (dot && !dot.found) {
-      ctx.lineTo(dot.x, dot.y);
-    }
+function drawHeatmap(ctx, w, h) {
+  if (!heatmap || heatmap.samples === 0) return;
+  const cw = w / heatmap.cols;
+  // Rows cover the zone height in square cells; the last may overhang
+  const [zw, zh] = currentPayload.zone_size;
+  const ch = (h / heatmap.cols) * (zw / zh);
+
+  ctx.save();
+  ctx.fillStyle = '#e67e80';
+  heatmap.cells.forEach((v, i) => {
+    if (v < 0.05) return;
+    ctx.globalAlpha = v * 0.5;
+    const c = i % heatmap.cols;
+    const r = Math.floor(i / heatmap.cols);
+    ctx.fillRect(c * cw, r * ch, cw, ch);
+  });
+  if (heatmap.peak) {
+    ctx.globalAlpha = 0.9;
+    ctx.strokeStyle = '#e67e80';
+    ctx.lineWidth = 2;
+    ctx.beginPath();
+    ctx.arc(heatmap.peak[0] * w, heatmap.peak[1] * h, 8, 0, Math.PI * 2);
+    ctx.stroke();
   }
+  ctx.restore();
+}
 
+function drawTrail(ctx, payload, w, h) {
+  if (payload.trail.length === 0) return;
+
+  ctx.save();
+  ctx.strokeStyle = '#dbbc7f';
+  ctx.fillStyle = '#dbbc7f';
+  ctx.lineWidth = 1;
+  ctx.globalAlpha = 0.4;
+  ctx.beginPath();
+  const points = [...payload.trail, payload.player_pos];
+  points.forEach(([x, y], i) => {
+    if (i === 0) ctx.moveTo(x * w, y * h);
+    else ctx.lineTo(x * w, y * h);
+  });
   ctx.stroke();
+  for (const [x, y] of payload.trail) {
+    ctx.beginPath();
+    ctx.arc(x * w, y * h, 2.5, 0, Math.PI * 2);
+    ctx.fill();
+  }
+  ctx.restore();
+}
+
+// Each motherlode reading is a ring of the reported distance around where it was taken
+function drawMotherlodes(ctx, payload, w, h) {
+  if (payload.motherlode_readings.length === 0) return;
+  const [zw, zh] = payload.zone_size;
+
+  ctx.save();
+  ctx.strokeStyle = '#d699b6';
+  ctx.lineWidth = 1;
+  ctx.setLineDash([2, 3]);
+  ctx.globalAlpha = 0.7;
+  for (const r of payload.motherlode_readings) {
+    ctx.beginPath();
+    ctx.ellipse(
+      r.pos[0] * w, r.pos[1] * h,
+      r.distance_m / zw * w, r.distance_m / zh * h,
+      0, 0, Math.PI * 2,
+    );
+    ctx.stroke();
+  }
+  ctx.restore();
 }
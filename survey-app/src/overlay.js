@@ -0,0 +1,28 @@
+// Survey Helper — compact always-on-top overlay
+// Mirrors the next leg and batch progress from `state-updated` events.
+
+const { invoke } = window.__TAURI__.core;
+const { listen } = window.__TAURI__.event;
+
+const $ = (s) => document.querySelector(s);
+
+function render(payload) {
+  const leg = payload.next_leg;
+  $('#arrow').hidden = !leg;
+  if (leg) {
+    $('#arrow').style.transform = `rotate(${leg.bearing}deg)`;
+    $('#leg').textContent = leg.text;
+  } else if (payload.mode === 'record') {
+    const rec = payload.record;
+    $('#leg').textContent = `Recording ${rec.buffered.length}/${rec.batch_size}`;
+  } else {
+    $('#leg').textContent = 'Route complete';
+  }
+  $('#progress').textContent = `${payload.zone} · ${payload.summary}`;
+}
+
+document.addEventListener('DOMContentLoaded', async () => {
+  await listen('state-updated', (event) => render(event.payload));
+  $('#close-btn').addEventListener('click', () => invoke('toggle_overlay'));
+  render(await invoke('get_render_state'));
+});